@@ -0,0 +1,727 @@
+use crate::checkpoint::{Checkpoint, CheckpointOptions, Checkpoints, Position};
+use crate::errors::{ErrorStats, OnError, Utf8Mode};
+use crate::filter::Filter;
+use crate::follow;
+use crate::input::{self, Chunk, Input};
+use crate::path::FieldPath;
+use crate::pipeline;
+use crate::process::{
+    count_lines, line_timestamp, new_scanner, process_chunk, scale_bytes, Worker,
+};
+use crate::report::Report;
+use crate::scan::Scanner;
+use crate::seek;
+use crate::snapshot::{Settings, Snapshot};
+use crate::stats::{ByteMode, Stats};
+use crate::time::{Buckets, TimestampFormat};
+use anyhow::{bail, Context, Result};
+use std::borrow::Cow;
+use std::ffi::OsStr;
+use std::fmt;
+use std::io;
+use std::io::Read;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering::Relaxed;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// The name of the input of [`Analyzer::feed_line`] and [`Analyzer::feed_reader`] in the
+/// locations of errors and in the group keys if the report is broken down by file.
+pub const INPUT_NAME: &str = "<input>";
+
+/// The options of an analysis. See the methods of [`AnalyzerBuilder`].
+#[derive(Debug)]
+pub struct Options {
+    pub keys: Vec<FieldPath>,
+    pub require_key: bool,
+    pub per_file: bool,
+    pub on_error: OnError,
+    pub utf8: Utf8Mode,
+    pub error_samples: usize,
+    pub bytes: ByteMode,
+    pub count_compressed: bool,
+    pub threads: usize,
+    pub mmap: bool,
+    pub filters: Vec<Filter>,
+    pub value: Option<FieldPath>,
+    pub exact_percentiles: bool,
+    pub distinct: Option<FieldPath>,
+    pub exact_distinct: bool,
+    pub timestamp: Option<FieldPath>,
+    pub timestamp_format: TimestampFormat,
+    pub bucket: Option<Buckets>,
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+    pub sorted: bool,
+    /// Whether the fields of the entries are collected in `TypeData::schema`.
+    pub schema: bool,
+    pub on_warning: Option<WarningHandler>,
+}
+
+impl Options {
+    /// The names of the components of the group keys.
+    pub(crate) fn key_fields(&self) -> Vec<String> {
+        let mut key_fields: Vec<_> = self.keys.iter().map(|k| k.to_string()).collect();
+        if self.per_file {
+            key_fields.insert(0, "file".to_string());
+        }
+        if self.bucket.is_some() {
+            key_fields.push("time".to_string());
+        }
+        key_fields
+    }
+
+    /// Passes warnings to the handler set with [`AnalyzerBuilder::on_warning`].
+    pub(crate) fn warn(&self, warnings: &[String]) {
+        if let Some(handler) = &self.on_warning {
+            for warning in warnings {
+                (handler.0)(warning);
+            }
+        }
+    }
+}
+
+/// The handler set with [`AnalyzerBuilder::on_warning`].
+#[derive(Clone)]
+pub struct WarningHandler(Arc<dyn Fn(&str) + Send + Sync>);
+
+impl fmt::Debug for WarningHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("WarningHandler")
+    }
+}
+
+/// Configures an [`Analyzer`].
+///
+/// The options correspond to the options of the command-line interface.
+#[derive(Debug)]
+pub struct AnalyzerBuilder {
+    options: Options,
+}
+
+impl AnalyzerBuilder {
+    /// Adds a field to group the entries by.
+    ///
+    /// If multiple fields are added, the entries are grouped by the combination of all fields.
+    /// Fields missing from an entry are grouped as absent values. Defaults to `type`.
+    pub fn key(mut self, key: FieldPath) -> Self {
+        self.options.keys.push(key);
+        self
+    }
+
+    /// Treats entries that contain none of the key fields as errors of the kind
+    /// [`ErrorKind::MissingKey`] instead of grouping them under a key where all fields are
+    /// absent.
+    ///
+    /// [`ErrorKind::MissingKey`]: crate::ErrorKind::MissingKey
+    pub fn require_key(mut self, require_key: bool) -> Self {
+        self.options.require_key = require_key;
+        self
+    }
+
+    /// Adds the name of the input as the first key field `file`, so no other key field may be
+    /// called `file`.
+    pub fn per_file(mut self, per_file: bool) -> Self {
+        self.options.per_file = per_file;
+        self
+    }
+
+    /// What to do with lines that cannot be processed. Defaults to [`OnError::Fail`].
+    pub fn on_error(mut self, on_error: OnError) -> Self {
+        self.options.on_error = on_error;
+        self
+    }
+
+    /// Calls `handler` with a warning for every line that is skipped because of
+    /// [`OnError::Warn`]. Warnings are discarded by default.
+    pub fn on_warning(mut self, handler: impl Fn(&str) + Send + Sync + 'static) -> Self {
+        self.options.on_warning = Some(WarningHandler(Arc::new(handler)));
+        self
+    }
+
+    /// How lines that are not valid UTF-8 are decoded. Defaults to [`Utf8Mode::Strict`].
+    ///
+    /// Lines that are not valid UTF-8 are counted in [`Report::invalid_utf8_lines`] regardless
+    /// of this option.
+    pub fn utf8(mut self, utf8: Utf8Mode) -> Self {
+        self.options.utf8 = utf8;
+        self
+    }
+
+    /// The maximum number of locations remembered per kind of error. Defaults to 10.
+    pub fn error_samples(mut self, error_samples: usize) -> Self {
+        self.options.error_samples = error_samples;
+        self
+    }
+
+    /// Which bytes of a line are counted as the size of the entry. Defaults to
+    /// [`ByteMode::Content`].
+    pub fn bytes(mut self, bytes: ByteMode) -> Self {
+        self.options.bytes = bytes;
+        self
+    }
+
+    /// Distributes the size of compressed inputs over their entries in proportion to their
+    /// decompressed sizes. This implies [`ByteMode::Raw`].
+    pub fn count_compressed(mut self, count_compressed: bool) -> Self {
+        self.options.count_compressed = count_compressed;
+        self
+    }
+
+    /// The number of threads used to parse inputs, which must not be zero. Defaults to the number
+    /// of CPUs. The results do not depend on the number of threads.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.options.threads = threads;
+        self
+    }
+
+    /// Whether regular files are mapped into memory. Defaults to true.
+    ///
+    /// Memory-mapped files are faster to process but the program might crash if another process
+    /// truncates a file while it is being analyzed.
+    pub fn mmap(mut self, mmap: bool) -> Self {
+        self.options.mmap = mmap;
+        self
+    }
+
+    /// Adds a filter that entries must match to be included.
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.options.filters.push(filter);
+        self
+    }
+
+    /// A numeric field to compute summary statistics of (see [`TypeData::values`]).
+    ///
+    /// [`TypeData::values`]: crate::TypeData::values
+    pub fn value(mut self, field: FieldPath) -> Self {
+        self.options.value = Some(field);
+        self
+    }
+
+    /// Computes the percentiles of the value field exactly instead of approximately. This
+    /// requires memory proportional to the number of entries.
+    pub fn exact_percentiles(mut self, exact: bool) -> Self {
+        self.options.exact_percentiles = exact;
+        self
+    }
+
+    /// A field whose distinct values are counted (see [`TypeData::distinct`]).
+    ///
+    /// [`TypeData::distinct`]: crate::TypeData::distinct
+    pub fn distinct(mut self, field: FieldPath) -> Self {
+        self.options.distinct = Some(field);
+        self
+    }
+
+    /// Counts the distinct values exactly instead of approximately. This requires memory
+    /// proportional to the number of distinct values.
+    pub fn exact_distinct(mut self, exact: bool) -> Self {
+        self.options.exact_distinct = exact;
+        self
+    }
+
+    /// A field containing the time of the entry. Required by the time range and buckets.
+    pub fn timestamp(mut self, field: FieldPath) -> Self {
+        self.options.timestamp = Some(field);
+        self
+    }
+
+    /// The format of the timestamp field. Defaults to [`TimestampFormat::Rfc3339`].
+    pub fn timestamp_format(mut self, format: TimestampFormat) -> Self {
+        self.options.timestamp_format = format;
+        self
+    }
+
+    /// Breaks down the report into time buckets. The start of the bucket is added as the last
+    /// key field `time`, so no other key field may be called `time`. The human and separated
+    /// formats of the [`Report`] show the types as rows and the buckets as columns.
+    pub fn bucket(mut self, buckets: Buckets) -> Self {
+        self.options.bucket = Some(buckets);
+        self
+    }
+
+    /// Excludes entries whose timestamps are before this time in milliseconds since the Unix
+    /// epoch.
+    pub fn since(mut self, since: i64) -> Self {
+        self.options.since = Some(since);
+        self
+    }
+
+    /// Excludes entries whose timestamps are at or after this time in milliseconds since the
+    /// Unix epoch.
+    pub fn until(mut self, until: i64) -> Self {
+        self.options.until = Some(until);
+        self
+    }
+
+    /// Assumes that the entries are sorted by their timestamps so that the time range can be
+    /// located by binary search in files that are mapped into memory. Lines outside of the
+    /// time range are then counted as excluded without being validated.
+    pub fn sorted(mut self, sorted: bool) -> Self {
+        self.options.sorted = sorted;
+        self
+    }
+
+    /// Collects the fields of the entries (see [`TypeData::schema`]). This is slower because
+    /// every entry has to be parsed completely.
+    ///
+    /// [`TypeData::schema`]: crate::TypeData::schema
+    pub fn schema(mut self, schema: bool) -> Self {
+        self.options.schema = schema;
+        self
+    }
+
+    /// Creates the analyzer. Fails if the options are inconsistent.
+    pub fn build(mut self) -> Result<Analyzer> {
+        let options = &mut self.options;
+        let needs_timestamp = options.bucket.is_some()
+            || options.since.is_some()
+            || options.until.is_some()
+            || options.sorted;
+        if needs_timestamp && options.timestamp.is_none() {
+            bail!("Time buckets, time ranges, and sorted inputs require a timestamp field");
+        }
+        if options.threads == 0 {
+            bail!("The number of threads must not be zero");
+        }
+        if options.keys.is_empty() {
+            options.keys.push("type".parse().unwrap());
+        }
+        // NOTE: The key fields are the names of the fields of the keys in the json formats.
+        let key_fields = options.key_fields();
+        for (i, field) in key_fields.iter().enumerate() {
+            if key_fields[..i].contains(field) {
+                bail!(
+                    "The key field `{}` occurs more than once. The name of the input and the \
+                     start of the time bucket are the key fields `file` and `time`.",
+                    field
+                );
+            }
+        }
+        if options.count_compressed {
+            options.bytes = ByteMode::Raw;
+        }
+        let options = Arc::new(self.options);
+        let scanner = Arc::new(new_scanner(&options));
+        Ok(Analyzer {
+            stats: Stats::new(ErrorStats::new(options.error_samples)),
+            worker: Worker::new(&options, &scanner, INPUT_NAME),
+            next_line: 1,
+            checkpoints: Some(vec![]),
+            resumable: vec![],
+            options,
+            scanner,
+        })
+    }
+}
+
+/// Analyzes the occurrences of entry types in logs containing one json object per line.
+///
+/// Lines can be passed individually, from readers, or from files. The results of all inputs are
+/// merged into a single [`Report`].
+pub struct Analyzer {
+    pub(crate) options: Arc<Options>,
+    pub(crate) scanner: Arc<Scanner>,
+    pub(crate) stats: Stats,
+    /// The worker processing the lines passed to `feed_line`.
+    worker: Worker,
+    /// The number of the next line passed to `feed_line` or `feed_reader`.
+    next_line: u64,
+    /// The positions up to which the files passed to `feed_file` have been processed. `None` if
+    /// other inputs have been processed.
+    checkpoints: Option<Vec<Checkpoint>>,
+    /// The checkpoints restored by `resume` of files that have not been passed to `feed_file`
+    /// since then.
+    resumable: Vec<Checkpoint>,
+}
+
+impl Analyzer {
+    /// Returns a builder with the default options. The entries are grouped by the field `type`
+    /// and parsed with one thread per CPU. Lines that cannot be processed abort the analysis.
+    pub fn builder() -> AnalyzerBuilder {
+        let threads = thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1);
+        AnalyzerBuilder {
+            options: Options {
+                keys: vec![],
+                require_key: false,
+                per_file: false,
+                on_error: OnError::Fail,
+                utf8: Utf8Mode::Strict,
+                error_samples: 10,
+                bytes: ByteMode::Content,
+                count_compressed: false,
+                threads,
+                mmap: true,
+                filters: vec![],
+                value: None,
+                exact_percentiles: false,
+                distinct: None,
+                exact_distinct: false,
+                timestamp: None,
+                timestamp_format: TimestampFormat::Rfc3339,
+                bucket: None,
+                since: None,
+                until: None,
+                sorted: false,
+                schema: false,
+                on_warning: None,
+            },
+        }
+    }
+
+    /// Processes a single line. A trailing `\n` or `\r\n` is ignored.
+    ///
+    /// Lines that cannot be processed are handled as configured with
+    /// [`AnalyzerBuilder::on_error`]. An error is returned if the analysis cannot continue.
+    pub fn feed_line(&mut self, line: &[u8]) -> Result<()> {
+        // NOTE: An empty chunk would not contain any lines.
+        let data = match line {
+            [] => b"\n",
+            _ => line,
+        };
+        let chunk = Chunk {
+            data: Cow::Borrowed(data),
+            first_line: self.next_line,
+        };
+        self.next_line += count_lines(data);
+        self.checkpoints = None;
+        self.process(&chunk)
+    }
+
+    /// Processes all lines of a reader. Compressed data is detected and decompressed.
+    ///
+    /// The lines are numbered after the lines previously passed to `feed_line` or
+    /// `feed_reader`.
+    pub fn feed_reader(&mut self, reader: impl Read + Send) -> Result<()> {
+        let stream = input::open_reader(reader).context("Could not read from the input")?;
+        self.checkpoints = None;
+        let input = Input::Stream(stream);
+        let mut position = Position::default();
+        self.next_line += self.feed_input(INPUT_NAME, input, self.next_line, &mut position)?;
+        Ok(())
+    }
+
+    /// Processes all lines of a file. `-` refers to stdin. Compressed files are detected and
+    /// decompressed.
+    ///
+    /// If the analysis has been resumed from a snapshot that contains the file, only the lines
+    /// appended since then are processed.
+    pub fn feed_file(&mut self, path: &OsStr) -> Result<()> {
+        let name = path.to_string_lossy();
+        let resumed = self.resumable.iter().position(|c| c.path == name);
+        let resumed = resumed.map(|i| self.resumable.remove(i));
+        if path == "-" {
+            self.checkpoints = None;
+        }
+        // NOTE: Compressed files are only resumed if they are unchanged.
+        if let Some(checkpoint) = resumed.as_ref().filter(|c| c.compressed) {
+            self.push_checkpoint(checkpoint.clone());
+            return Ok(());
+        }
+        let metadata = std::fs::metadata(path);
+        let input = input::open(path, self.options.mmap)?;
+        let compressed = match &input {
+            Input::Stream(stream) => stream.compressed_bytes.is_some(),
+            Input::Mapped(_) => false,
+        };
+        let (mut position, lines) = match &resumed {
+            Some(c) => (c.position.clone(), c.lines),
+            None => (Position::default(), 0),
+        };
+        let lines = lines + self.feed_input(&name, input, lines + 1, &mut position)?;
+        if let (Ok(metadata), true) = (&metadata, compressed) {
+            position.offset = metadata.len();
+        }
+        if let Ok(metadata) = metadata {
+            self.push_checkpoint(Checkpoint {
+                path: name.to_string(),
+                id: input::file_id(&metadata),
+                compressed,
+                lines,
+                position,
+            });
+        }
+        Ok(())
+    }
+
+    /// Records the position up to which a file has been processed.
+    fn push_checkpoint(&mut self, checkpoint: Checkpoint) {
+        if let Some(checkpoints) = &mut self.checkpoints {
+            // NOTE: Files that are processed multiple times cannot be resumed.
+            match checkpoints.iter().any(|c| c.path == checkpoint.path) {
+                true => self.checkpoints = None,
+                false => checkpoints.push(checkpoint),
+            }
+        }
+    }
+
+    /// Restores the state of a snapshot so that [`Analyzer::feed_file`] only processes the lines
+    /// appended to the files of the snapshot since then. This replaces the current state.
+    ///
+    /// Returns false and leaves the analyzer unchanged if the snapshot cannot be resumed with
+    /// `files`, the files that will be processed. This is the case if the snapshot was created
+    /// with different options, if it includes inputs other than files, or if one of its files is
+    /// not in `files`, has been replaced, or has been truncated. Then all files have to be
+    /// processed from the start.
+    pub fn resume(&mut self, snapshot: Snapshot, files: &[&OsStr]) -> bool {
+        let checkpoints = match snapshot.checkpoints {
+            Some(checkpoints) => checkpoints,
+            None => return false,
+        };
+        if checkpoints.options != CheckpointOptions::new(&self.options)
+            || snapshot.settings != Settings::new(&self.options)
+        {
+            return false;
+        }
+        let valid = checkpoints.files.iter().all(|checkpoint| {
+            let file = files
+                .iter()
+                .find(|f| f.to_string_lossy() == checkpoint.path);
+            file.is_some_and(|file| checkpoint.is_valid(file))
+        });
+        if !valid {
+            return false;
+        }
+        self.stats = snapshot.stats;
+        self.checkpoints = Some(vec![]);
+        self.resumable = checkpoints.files;
+        true
+    }
+
+    /// Processes a file that is still being written to until `stop` is set. Then the rest of the
+    /// file is processed.
+    ///
+    /// If the file is replaced (e.g. by log rotation) or truncated, it is processed again from
+    /// the start. `render` is called with the analyzer every `interval`, which must not be zero.
+    /// Compressed files are rejected.
+    pub fn follow_file(
+        &mut self,
+        path: &OsStr,
+        interval: Duration,
+        stop: &AtomicBool,
+        render: impl FnMut(&Analyzer) -> Result<()>,
+    ) -> Result<()> {
+        if interval.is_zero() {
+            bail!("The interval must not be zero");
+        }
+        self.checkpoints = None;
+        follow::follow_file(self, path, interval, stop, render)
+    }
+
+    /// Returns the report of all lines processed so far. The types are sorted by their keys.
+    pub fn report(&self) -> Report {
+        Settings::new(&self.options).report(&self.stats)
+    }
+
+    /// Returns the aggregated state of all lines processed so far. See [`Snapshot`].
+    pub fn snapshot(&self) -> Snapshot {
+        // NOTE: Files that were restored but not processed again are still part of the state.
+        let checkpoints = self.checkpoints.as_ref().map(|files| Checkpoints {
+            options: CheckpointOptions::new(&self.options),
+            files: files.iter().chain(&self.resumable).cloned().collect(),
+        });
+        Snapshot::new(
+            Settings::new(&self.options),
+            self.stats.clone(),
+            checkpoints,
+        )
+    }
+
+    fn process(&mut self, chunk: &Chunk) -> Result<()> {
+        let (chunk_stats, warnings) = process_chunk(&mut self.worker, chunk)?;
+        self.options.warn(&warnings);
+        self.stats.merge(chunk_stats);
+        Ok(())
+    }
+
+    /// Processes an input starting at the offset of `position`, which is advanced to the end of
+    /// the input. The line at the offset has the number `first_line`. Returns the number of lines
+    /// that were processed.
+    fn feed_input(
+        &mut self,
+        name: &str,
+        mut input: Input,
+        first_line: u64,
+        position: &mut Position,
+    ) -> Result<u64> {
+        let options = self.options.clone();
+        let mut file_stats = Stats::new(ErrorStats::new(options.error_samples));
+        let mut next_line = first_line;
+        let mut decompressed_bytes = 0;
+        let start = position.offset;
+        let (mut map, mut stream) = match &mut input {
+            Input::Mapped(map) => {
+                let map = match map.get(start as usize..) {
+                    Some(map) => map,
+                    None => bail!("The file is shorter than the checkpoint"),
+                };
+                let has_range = options.since.is_some() || options.until.is_some();
+                let window = match options.sorted && has_range {
+                    true => seek::find_window(map, options.since, options.until, |line| {
+                        line_timestamp(&options, line)
+                    }),
+                    false => 0..map.len(),
+                };
+                // NOTE: The lines outside of the window are counted without being processed.
+                let excluded = count_lines(&map[..window.start]) + count_lines(&map[window.end..]);
+                let excluded_bytes = (map.len() - window.len()) as u64;
+                file_stats.lines += excluded;
+                file_stats.excluded += excluded;
+                file_stats.read_bytes += excluded_bytes;
+                file_stats.input_bytes += excluded_bytes;
+                file_stats.other_bytes += excluded_bytes;
+                next_line += count_lines(&map[..window.start]);
+                (Some((&map[..window.end], window.start)), None)
+            }
+            Input::Stream(stream) => {
+                let skipped = io::copy(&mut (&mut stream.reader).take(start), &mut io::sink())
+                    .context("Could not read from the file")?;
+                if skipped < start {
+                    bail!("The file is shorter than the checkpoint");
+                }
+                (None, Some(stream))
+            }
+        };
+        // NOTE: Buffers of processed chunks are reused for new chunks.
+        let buffers = Mutex::new(vec![]);
+        pipeline::run(
+            options.threads,
+            || {
+                let chunk = match (&mut map, &mut stream) {
+                    (Some((map, pos)), _) => input::next_mapped_chunk(map, pos, next_line),
+                    (_, Some(stream)) => {
+                        let buffer = buffers.lock().unwrap().pop().unwrap_or_default();
+                        input::read_chunk(&mut stream.reader, buffer, next_line)
+                            .context("Could not read from the file")?
+                    }
+                    _ => unreachable!(),
+                };
+                if let Some(chunk) = &chunk {
+                    next_line += memchr::memchr_iter(b'\n', &chunk.data).count() as u64;
+                    decompressed_bytes += chunk.data.len() as u64;
+                    if stream.is_some() {
+                        position.advance(&chunk.data);
+                    }
+                }
+                Ok(chunk)
+            },
+            || Worker::new(&options, &self.scanner, name),
+            |worker, chunk| {
+                let res = process_chunk(worker, &chunk);
+                if let Cow::Owned(buffer) = chunk.data {
+                    buffers.lock().unwrap().push(buffer);
+                }
+                res
+            },
+            |(chunk_stats, warnings)| {
+                options.warn(&warnings);
+                file_stats.merge(chunk_stats);
+                Ok(())
+            },
+        )?;
+        // NOTE: The sizes of the entries in compressed files are scaled once the size of the
+        // compressed file is known.
+        let compressed_bytes = match &input {
+            Input::Stream(stream) => stream.compressed_bytes.as_ref(),
+            Input::Mapped(map) => {
+                position.advance(&map[start as usize..]);
+                None
+            }
+        };
+        if let Some(compressed_bytes) = compressed_bytes {
+            let compressed_bytes = compressed_bytes.load(Relaxed);
+            if options.count_compressed {
+                scale_bytes(&mut file_stats, compressed_bytes, decompressed_bytes);
+            }
+            file_stats.input_bytes = compressed_bytes;
+        }
+        let lines = file_stats.lines;
+        self.stats.merge(file_stats);
+        Ok(lines)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn warnings_are_passed_to_the_handler() {
+        let warnings = Arc::new(Mutex::new(vec![]));
+        let handler = {
+            let warnings = warnings.clone();
+            move |warning: &str| warnings.lock().unwrap().push(warning.to_string())
+        };
+        let mut analyzer = Analyzer::builder()
+            .on_error(OnError::Warn)
+            .on_warning(handler)
+            .build()
+            .unwrap();
+        analyzer
+            .feed_reader(&b"{\"type\":\"a\"}\nnot json\n{\"type\":\"b\"}\n"[..])
+            .unwrap();
+        analyzer.feed_line(b"[").unwrap();
+        let warnings = warnings.lock().unwrap();
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].starts_with("Skipping line number 2 of <input>"));
+        assert_eq!(analyzer.report().total().objects(), 2);
+    }
+
+    #[test]
+    fn duplicate_key_fields_are_rejected() {
+        let builder = || {
+            Analyzer::builder()
+                .key("type".parse().unwrap())
+                .timestamp("ts".parse().unwrap())
+        };
+        let e = builder()
+            .key("type".parse().unwrap())
+            .build()
+            .err()
+            .unwrap();
+        assert!(e
+            .to_string()
+            .starts_with("The key field `type` occurs more than once."));
+        let e = builder()
+            .key("time".parse().unwrap())
+            .bucket("1h".parse().unwrap())
+            .build()
+            .err()
+            .unwrap();
+        assert!(e
+            .to_string()
+            .starts_with("The key field `time` occurs more than once."));
+        let e = builder()
+            .key("file".parse().unwrap())
+            .per_file(true)
+            .build()
+            .err()
+            .unwrap();
+        assert!(e
+            .to_string()
+            .starts_with("The key field `file` occurs more than once."));
+        // NOTE: A different spelling of the same field is a different name in the report.
+        let analyzer = builder()
+            .key("/time".parse().unwrap())
+            .bucket("1h".parse().unwrap())
+            .build()
+            .unwrap();
+        assert_eq!(analyzer.report().key_fields(), ["type", "/time", "time"]);
+        let analyzer = builder()
+            .key("/file".parse().unwrap())
+            .per_file(true)
+            .build()
+            .unwrap();
+        assert_eq!(analyzer.report().key_fields(), ["file", "type", "/file"]);
+    }
+
+    #[test]
+    fn zero_threads_are_rejected() {
+        let e = Analyzer::builder().threads(0).build().err().unwrap();
+        assert_eq!(e.to_string(), "The number of threads must not be zero");
+        assert!(Analyzer::builder().threads(1).build().is_ok());
+    }
+}
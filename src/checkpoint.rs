@@ -0,0 +1,329 @@
+use crate::analyzer::Options;
+use crate::distinct::hash_bytes;
+use crate::errors::{OnError, Utf8Mode};
+use crate::input::{file_id, FileId};
+use serde::{Deserialize, Serialize};
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+
+/// The positions up to which the files of an analysis have been processed.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct Checkpoints {
+    pub options: CheckpointOptions,
+    pub files: Vec<Checkpoint>,
+}
+
+/// The options that affect which lines are counted. Checkpoints can only be resumed with the same
+/// options.
+///
+/// The remaining options that affect the aggregated state are part of the settings of the
+/// snapshot.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct CheckpointOptions {
+    keys: Vec<String>,
+    require_key: bool,
+    per_file: bool,
+    on_error: OnError,
+    utf8: Utf8Mode,
+    /// The filter expressions as written.
+    filters: Vec<String>,
+    timestamp: Option<String>,
+    timestamp_format: String,
+    /// The width of the time buckets in milliseconds.
+    bucket: Option<i64>,
+    /// The time range in milliseconds. A range relative to the current time would differ between
+    /// runs and is therefore rejected together with checkpoints by the CLI.
+    since: Option<i64>,
+    until: Option<i64>,
+    sorted: bool,
+}
+
+impl CheckpointOptions {
+    pub(crate) fn new(options: &Options) -> Self {
+        Self {
+            keys: options.keys.iter().map(|k| k.to_string()).collect(),
+            require_key: options.require_key,
+            per_file: options.per_file,
+            on_error: options.on_error,
+            utf8: options.utf8,
+            filters: options.filters.iter().map(|f| f.to_string()).collect(),
+            timestamp: options.timestamp.as_ref().map(|t| t.to_string()),
+            timestamp_format: options.timestamp_format.to_string(),
+            bucket: options.bucket.as_ref().map(|b| b.width()),
+            since: options.since,
+            until: options.until,
+            sorted: options.sorted,
+        }
+    }
+}
+
+/// The position up to which a file has been processed.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct Checkpoint {
+    /// The name of the file as passed to `Analyzer::feed_file`.
+    pub path: String,
+    /// The identity of the file. `None` on platforms where files cannot be identified.
+    pub id: Option<FileId>,
+    /// Whether the file is compressed. Compressed files cannot be resumed and are only skipped if
+    /// they are unchanged.
+    pub compressed: bool,
+    /// The number of lines that have been processed.
+    pub lines: u64,
+    #[serde(flatten)]
+    pub position: Position,
+}
+
+impl Checkpoint {
+    /// Returns whether the file still starts with the processed data, i.e. whether it has neither
+    /// been replaced nor truncated, so that lines appended since then can be processed by resuming
+    /// at the offset.
+    pub fn is_valid(&self, path: &OsStr) -> bool {
+        self.check(path).unwrap_or(false)
+    }
+
+    fn check(&self, path: &OsStr) -> io::Result<bool> {
+        let mut file = File::open(path)?;
+        let metadata = file.metadata()?;
+        let position = &self.position;
+        if file_id(&metadata) != self.id || metadata.len() < position.offset {
+            return Ok(false);
+        }
+        if self.compressed {
+            return Ok(metadata.len() == position.offset);
+        }
+        let mut line = vec![0; (position.offset - position.line_start) as usize];
+        file.seek(SeekFrom::Start(position.line_start))?;
+        file.read_exact(&mut line)?;
+        if hash_bytes(&line) != position.line_hash {
+            return Ok(false);
+        }
+        // NOTE: If the last line was incomplete, the data appended since then might complete it.
+        let complete = line.last().is_none_or(|&b| b == b'\n');
+        Ok(complete || metadata.len() == position.offset)
+    }
+}
+
+/// The end of the processed data of a file and its last line.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct Position {
+    /// The number of bytes that have been processed. For compressed files, this is the size of
+    /// the file.
+    pub offset: u64,
+    /// The offset of the last processed line.
+    pub line_start: u64,
+    /// The hash of the last processed line including its terminator.
+    pub line_hash: u64,
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Self {
+            offset: 0,
+            line_start: 0,
+            line_hash: hash_bytes(&[]),
+        }
+    }
+}
+
+impl Position {
+    /// Advances over data that starts at a line boundary.
+    pub fn advance(&mut self, data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+        let end = data.len() - (data.last() == Some(&b'\n')) as usize;
+        let start = memchr::memrchr(b'\n', &data[..end]).map_or(0, |n| n + 1);
+        self.line_start = self.offset + start as u64;
+        self.line_hash = hash_bytes(&data[start..]);
+        self.offset += data.len() as u64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::{Analyzer, AnalyzerBuilder};
+    use crate::test_util::TempFile;
+
+    /// Returns the checkpoint after processing the whole file.
+    fn checkpoint(file: &TempFile, compressed: bool) -> Checkpoint {
+        let data = std::fs::read(&file.0).unwrap();
+        let mut position = Position::default();
+        position.advance(&data);
+        Checkpoint {
+            path: file.path().to_string(),
+            id: file_id(&std::fs::metadata(&file.0).unwrap()),
+            compressed,
+            lines: 0,
+            position,
+        }
+    }
+
+    fn is_valid(file: &TempFile, checkpoint: &Checkpoint) -> bool {
+        checkpoint.is_valid(file.0.as_os_str())
+    }
+
+    #[test]
+    fn appended_lines() {
+        let file = TempFile::new("checkpoint-appended", b"a\nbb\n");
+        let checkpoint = checkpoint(&file, false);
+        assert!(is_valid(&file, &checkpoint));
+        file.append(b"ccc\n");
+        assert!(is_valid(&file, &checkpoint));
+    }
+
+    #[test]
+    fn empty_file() {
+        let file = TempFile::new("checkpoint-empty", b"");
+        let checkpoint = checkpoint(&file, false);
+        assert!(is_valid(&file, &checkpoint));
+        file.append(b"a\n");
+        assert!(is_valid(&file, &checkpoint));
+    }
+
+    #[test]
+    fn truncated_file() {
+        let file = TempFile::new("checkpoint-truncated", b"a\nbb\n");
+        let checkpoint = checkpoint(&file, false);
+        file.overwrite(b"a\n");
+        assert!(!is_valid(&file, &checkpoint));
+    }
+
+    #[test]
+    fn rewritten_file() {
+        let file = TempFile::new("checkpoint-rewritten", b"a\nbb\n");
+        let checkpoint = checkpoint(&file, false);
+        file.overwrite(b"a\ncc\ndd\n");
+        assert!(!is_valid(&file, &checkpoint));
+        // NOTE: Only the last processed line is compared.
+        file.overwrite(b"x\nbb\ndd\n");
+        assert!(is_valid(&file, &checkpoint));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn replaced_file() {
+        let file = TempFile::new("checkpoint-replaced", b"a\nbb\n");
+        let checkpoint = checkpoint(&file, false);
+        let replacement = TempFile::new("checkpoint-replacement", b"a\nbb\ncc\n");
+        std::fs::rename(&replacement.0, &file.0).unwrap();
+        assert!(!is_valid(&file, &checkpoint));
+    }
+
+    #[test]
+    fn missing_file() {
+        let file = TempFile::new("checkpoint-missing", b"a\n");
+        let checkpoint = checkpoint(&file, false);
+        std::fs::remove_file(&file.0).unwrap();
+        assert!(!is_valid(&file, &checkpoint));
+    }
+
+    #[test]
+    fn incomplete_last_line() {
+        let file = TempFile::new("checkpoint-incomplete", b"a\nbb");
+        let checkpoint = checkpoint(&file, false);
+        assert!(is_valid(&file, &checkpoint));
+        file.append(b"b\n");
+        assert!(!is_valid(&file, &checkpoint));
+    }
+
+    #[test]
+    fn compressed_file() {
+        let file = TempFile::new("checkpoint-compressed", b"compressed data");
+        let checkpoint = checkpoint(&file, true);
+        assert!(is_valid(&file, &checkpoint));
+        file.append(b"more");
+        assert!(!is_valid(&file, &checkpoint));
+    }
+
+    #[test]
+    fn advance() {
+        let data = b"a\nbb\n\nccc\ndd";
+        let mut position = Position::default();
+        position.advance(&data[..5]);
+        assert_eq!((position.offset, position.line_start), (5, 2));
+        assert_eq!(position.line_hash, hash_bytes(b"bb\n"));
+        position.advance(&[]);
+        assert_eq!(position.offset, 5);
+        position.advance(&data[5..6]);
+        assert_eq!((position.offset, position.line_start), (6, 5));
+        assert_eq!(position.line_hash, hash_bytes(b"\n"));
+        position.advance(&data[6..]);
+        assert_eq!((position.offset, position.line_start), (12, 10));
+        assert_eq!(position.line_hash, hash_bytes(b"dd"));
+        let mut whole = Position::default();
+        whole.advance(data);
+        assert_eq!(
+            (whole.offset, whole.line_start, whole.line_hash),
+            (position.offset, position.line_start, position.line_hash)
+        );
+    }
+
+    fn options(builder: AnalyzerBuilder) -> CheckpointOptions {
+        CheckpointOptions::new(&builder.build().unwrap().options)
+    }
+
+    #[test]
+    fn options_identify_the_counted_lines() {
+        let filtered = |filter: &str| Analyzer::builder().filter(filter.parse().unwrap());
+        let bucketed = |width: &str| {
+            Analyzer::builder()
+                .timestamp("time".parse().unwrap())
+                .bucket(width.parse().unwrap())
+        };
+        assert_eq!(options(filtered("a == 1")), options(filtered("a == 1")));
+        assert_ne!(options(filtered("a == 1")), options(filtered("a == 2")));
+        assert_ne!(options(filtered("a == 1")), options(Analyzer::builder()));
+        assert_eq!(options(bucketed("60s")), options(bucketed("1m")));
+        assert_ne!(options(bucketed("1m")), options(bucketed("1h")));
+        assert_ne!(
+            options(Analyzer::builder().key("/a".parse().unwrap())),
+            options(Analyzer::builder().key("b".parse().unwrap()))
+        );
+        assert_ne!(
+            options(Analyzer::builder().utf8(Utf8Mode::Lossy)),
+            options(Analyzer::builder())
+        );
+        assert_ne!(
+            options(Analyzer::builder().on_error(OnError::Skip)),
+            options(Analyzer::builder())
+        );
+    }
+
+    #[test]
+    fn options_are_serialized_explicitly() {
+        let options = options(
+            Analyzer::builder()
+                .filter(r#"level != "debug""#.parse().unwrap())
+                .timestamp("time".parse().unwrap())
+                .timestamp_format("%Y-%m-%d".parse().unwrap())
+                .bucket("1h".parse().unwrap())
+                .since(1000)
+                .sorted(true),
+        );
+        let json = serde_json::to_value(&options).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "keys": ["type"],
+                "require_key": false,
+                "per_file": false,
+                "on_error": "fail",
+                "utf8": "strict",
+                "filters": [r#"level != "debug""#],
+                "timestamp": "time",
+                "timestamp_format": "%Y-%m-%d",
+                "bucket": 3_600_000,
+                "since": 1000,
+                "until": null,
+                "sorted": true,
+            })
+        );
+        assert_eq!(
+            serde_json::from_value::<CheckpointOptions>(json).unwrap(),
+            options
+        );
+    }
+}
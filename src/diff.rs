@@ -0,0 +1,685 @@
+use crate::key::{display_component, GroupKey};
+use crate::report::{
+    csv_escape, key_object, key_title, percent_change, tsv_escape, write_ndjson_record, Format,
+    Report, SCHEMA_VERSION,
+};
+use crate::schema::{Kind, Schema};
+use crate::stats::TypeData;
+use crate::table::{Align, Table};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::io::Write;
+
+/// The conditions checked by [`Diff::violations`]. The `diff` subcommand fails if one of them is
+/// met.
+#[derive(Debug, Default)]
+#[non_exhaustive]
+pub struct Thresholds {
+    /// Fail if a type occurs only in the new input
+    pub fail_on_added_types: bool,
+    /// Fail if a type occurs only in the old input
+    pub fail_on_removed_types: bool,
+    /// Fail if a field of a type occurring in both inputs was added or removed or its kinds
+    /// changed
+    pub fail_on_field_changes: bool,
+    /// Fail if the number of objects of a type occurring in both inputs changed by more than
+    /// this percentage
+    pub max_objects_change: Option<f64>,
+    /// Fail if the number of bytes of a type occurring in both inputs changed by more than this
+    /// percentage
+    pub max_bytes_change: Option<f64>,
+}
+
+/// The differences between the results of two analyses.
+pub struct Diff {
+    key_fields: Vec<String>,
+    /// The types occurring in either input ordered by their keys.
+    types: Vec<TypeDiff>,
+    old_total: Counts,
+    new_total: Counts,
+}
+
+#[derive(Copy, Clone, Debug, Default, Serialize)]
+struct Counts {
+    objects: u64,
+    bytes: u64,
+}
+
+impl Counts {
+    fn of(data: &TypeData) -> Self {
+        Self {
+            objects: data.num,
+            bytes: data.bytes,
+        }
+    }
+}
+
+struct TypeDiff {
+    key: GroupKey,
+    /// `None` if the type does not occur in the old input.
+    old: Option<Counts>,
+    /// `None` if the type does not occur in the new input.
+    new: Option<Counts>,
+    /// The changed fields ordered by their paths. Only computed for types occurring in both
+    /// inputs.
+    fields: Vec<FieldChange>,
+}
+
+/// A field whose kinds differ between the inputs.
+struct FieldChange {
+    path: String,
+    /// The kinds of the field in the old input. Empty if the field was added.
+    old: Vec<Kind>,
+    /// The kinds of the field in the new input. Empty if the field was removed.
+    new: Vec<Kind>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    Added,
+    Removed,
+    Changed,
+    Unchanged,
+}
+
+impl Status {
+    fn name(self) -> &'static str {
+        match self {
+            Status::Added => "added",
+            Status::Removed => "removed",
+            Status::Changed => "changed",
+            Status::Unchanged => "unchanged",
+        }
+    }
+}
+
+impl TypeDiff {
+    fn status(&self) -> Status {
+        match (self.old, self.new) {
+            (None, _) => Status::Added,
+            (_, None) => Status::Removed,
+            (Some(old), Some(new)) => {
+                let same = old.objects == new.objects && old.bytes == new.bytes;
+                match same && self.fields.is_empty() {
+                    true => Status::Unchanged,
+                    false => Status::Changed,
+                }
+            }
+        }
+    }
+}
+
+impl Diff {
+    /// Compares the reports of two analyses with the same key fields. The schemas of the types
+    /// must have been collected. Types removed by [`Report::limit`] are ignored.
+    pub fn new(old: &Report, new: &Report) -> Self {
+        let old_types: BTreeMap<_, _> = old.types().iter().map(|(k, v)| (k, v)).collect();
+        let new_types: BTreeMap<_, _> = new.types().iter().map(|(k, v)| (k, v)).collect();
+        let keys: BTreeSet<_> = old_types.keys().chain(new_types.keys()).collect();
+        let mut old_total = Counts::default();
+        let mut new_total = Counts::default();
+        let mut types = vec![];
+        for &key in keys {
+            let old = old_types.get(key).copied();
+            let new = new_types.get(key).copied();
+            for (total, data) in [(&mut old_total, old), (&mut new_total, new)] {
+                if let Some(data) = data {
+                    total.objects += data.num;
+                    total.bytes += data.bytes;
+                }
+            }
+            let fields = match (old, new) {
+                (Some(old), Some(new)) => field_changes(&old.schema, &new.schema),
+                _ => vec![],
+            };
+            types.push(TypeDiff {
+                key: (*key).clone(),
+                old: old.map(Counts::of),
+                new: new.map(Counts::of),
+                fields,
+            });
+        }
+        Self {
+            key_fields: old.key_fields().to_vec(),
+            types,
+            old_total,
+            new_total,
+        }
+    }
+
+    /// Returns a description of each condition of `thresholds` that is violated.
+    pub fn violations(&self, thresholds: &Thresholds) -> Vec<String> {
+        let mut violations = vec![];
+        for ty in &self.types {
+            let title = key_title(&self.key_fields, &ty.key);
+            match ty.status() {
+                Status::Added if thresholds.fail_on_added_types => {
+                    violations.push(format!("The type `{}` was added", title));
+                }
+                Status::Removed if thresholds.fail_on_removed_types => {
+                    violations.push(format!("The type `{}` was removed", title));
+                }
+                Status::Changed => {
+                    if thresholds.fail_on_field_changes && !ty.fields.is_empty() {
+                        violations.push(format!("The fields of the type `{}` changed", title));
+                    }
+                    let (old, new) = (ty.old.unwrap_or_default(), ty.new.unwrap_or_default());
+                    let changes = [
+                        (
+                            "objects",
+                            old.objects,
+                            new.objects,
+                            thresholds.max_objects_change,
+                        ),
+                        ("bytes", old.bytes, new.bytes, thresholds.max_bytes_change),
+                    ];
+                    for (what, old, new, max) in changes {
+                        let Some(max) = max else {
+                            continue;
+                        };
+                        match percent_change(old, new) {
+                            Some(change) if change.abs() > max => violations.push(format!(
+                                "The number of {} of the type `{}` changed by {:+.2}% (maximum {}%)",
+                                what, title, change, max
+                            )),
+                            // NOTE: A change from 0 exceeds every percentage.
+                            None if new > 0 => violations.push(format!(
+                                "The number of {} of the type `{}` changed from 0 to {} (maximum {}%)",
+                                what, title, new, max
+                            )),
+                            _ => {}
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        violations
+    }
+
+    pub fn write(&self, format: Format, w: &mut impl Write) -> io::Result<()> {
+        match format {
+            Format::Human => self.write_human(w),
+            Format::Json => {
+                let doc = JsonDiff {
+                    schema_version: SCHEMA_VERSION,
+                    key_fields: &self.key_fields,
+                    types: self.types.iter().map(|ty| self.json_type(ty)).collect(),
+                    totals: self.json_totals(),
+                };
+                serde_json::to_writer_pretty(&mut *w, &doc)?;
+                writeln!(w)
+            }
+            Format::Csv => self.write_separated(w, ',', csv_escape),
+            Format::Tsv => self.write_separated(w, '\t', tsv_escape),
+            Format::Ndjson => {
+                for ty in &self.types {
+                    write_ndjson_record(w, "type", self.json_type(ty))?;
+                }
+                write_ndjson_record(w, "totals", self.json_totals())
+            }
+        }
+    }
+
+    fn write_human(&self, w: &mut impl Write) -> io::Result<()> {
+        let mut columns: Vec<_> = self
+            .key_fields
+            .iter()
+            .map(|k| (k.clone(), Align::Left))
+            .collect();
+        columns.push(("Status".to_string(), Align::Left));
+        for column in ["Old Objects", "New Objects", "Objects Change"] {
+            columns.push((column.to_string(), Align::Right));
+        }
+        for column in ["Old Bytes", "New Bytes", "Bytes Change"] {
+            columns.push((column.to_string(), Align::Right));
+        }
+        let mut table = Table::new(columns);
+        let counts_cells = |old: Option<Counts>, new: Option<Counts>| {
+            let cell = |c: Option<Counts>, f: fn(Counts) -> u64| match c {
+                Some(c) => f(c).to_string(),
+                None => "-".to_string(),
+            };
+            let old_or_zero = old.unwrap_or_default();
+            let new_or_zero = new.unwrap_or_default();
+            vec![
+                cell(old, |c| c.objects),
+                cell(new, |c| c.objects),
+                format_change(old_or_zero.objects, new_or_zero.objects),
+                cell(old, |c| c.bytes),
+                cell(new, |c| c.bytes),
+                format_change(old_or_zero.bytes, new_or_zero.bytes),
+            ]
+        };
+        for ty in &self.types {
+            let mut row: Vec<_> = ty.key.iter().map(display_component).collect();
+            row.push(ty.status().name().to_string());
+            row.extend(counts_cells(ty.old, ty.new));
+            table.push(row);
+        }
+        let mut total = vec![String::new(); self.key_fields.len()];
+        if let Some(first) = total.first_mut() {
+            *first = "<total>".to_string();
+        }
+        total.push(String::new());
+        total.extend(counts_cells(Some(self.old_total), Some(self.new_total)));
+        table.push(total);
+        table.write(w)?;
+        for ty in self.types.iter().filter(|ty| !ty.fields.is_empty()) {
+            writeln!(w)?;
+            writeln!(
+                w,
+                "Field changes of `{}`:",
+                key_title(&self.key_fields, &ty.key)
+            )?;
+            for field in &ty.fields {
+                match (&field.old[..], &field.new[..]) {
+                    ([], new) => writeln!(w, "  + {} ({})", field.path, kind_names(new))?,
+                    (old, []) => writeln!(w, "  - {} ({})", field.path, kind_names(old))?,
+                    (old, new) => writeln!(
+                        w,
+                        "  ~ {} ({} -> {})",
+                        field.path,
+                        kind_names(old),
+                        kind_names(new)
+                    )?,
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn json_type(&self, ty: &TypeDiff) -> JsonTypeDiff {
+        let mut fields = JsonFieldChanges::default();
+        for field in &ty.fields {
+            let kinds = |kinds: &[Kind]| kinds.iter().map(|k| k.name()).collect();
+            match (&field.old[..], &field.new[..]) {
+                ([], new) => fields.added.push(JsonField {
+                    path: field.path.clone(),
+                    kinds: kinds(new),
+                }),
+                (old, []) => fields.removed.push(JsonField {
+                    path: field.path.clone(),
+                    kinds: kinds(old),
+                }),
+                (old, new) => fields.changed.push(JsonKindChange {
+                    path: field.path.clone(),
+                    old_kinds: kinds(old),
+                    new_kinds: kinds(new),
+                }),
+            }
+        }
+        JsonTypeDiff {
+            key: key_object(&self.key_fields, &ty.key),
+            status: ty.status(),
+            changes: json_changes(ty.old, ty.new),
+            fields,
+        }
+    }
+
+    fn json_totals(&self) -> JsonChanges {
+        json_changes(Some(self.old_total), Some(self.new_total))
+    }
+
+    /// Writes one row per type. Key fields are written as by the `csv` and `tsv` formats of the
+    /// report. The changed fields are written as json arrays of paths.
+    fn write_separated(
+        &self,
+        w: &mut impl Write,
+        separator: char,
+        escape: fn(&str) -> String,
+    ) -> io::Result<()> {
+        let mut write_row = |cells: Vec<String>| {
+            let cells: Vec<_> = cells.iter().map(|c| escape(c)).collect();
+            writeln!(w, "{}", cells.join(&separator.to_string()))
+        };
+        let mut header = vec!["schema_version".to_string()];
+        header.extend(self.key_fields.iter().cloned());
+        for column in [
+            "status",
+            "old_objects",
+            "new_objects",
+            "objects_change",
+            "objects_change_percent",
+            "old_bytes",
+            "new_bytes",
+            "bytes_change",
+            "bytes_change_percent",
+            "added_fields",
+            "removed_fields",
+            "changed_fields",
+        ] {
+            header.push(column.to_string());
+        }
+        write_row(header)?;
+        for ty in &self.types {
+            let mut row = vec![SCHEMA_VERSION.to_string()];
+            row.extend(
+                ty.key
+                    .iter()
+                    .map(|v| v.as_ref().map(|v| v.to_string()).unwrap_or_default()),
+            );
+            row.push(ty.status().name().to_string());
+            let optional = |v: Option<f64>| v.map(|v| v.to_string()).unwrap_or_default();
+            let changes = json_changes(ty.old, ty.new);
+            let count = |c: Option<Counts>, f: fn(Counts) -> u64| {
+                c.map(|c| f(c).to_string()).unwrap_or_default()
+            };
+            row.push(count(ty.old, |c| c.objects));
+            row.push(count(ty.new, |c| c.objects));
+            row.push(changes.objects_change.to_string());
+            row.push(optional(changes.objects_change_percent));
+            row.push(count(ty.old, |c| c.bytes));
+            row.push(count(ty.new, |c| c.bytes));
+            row.push(changes.bytes_change.to_string());
+            row.push(optional(changes.bytes_change_percent));
+            let fields = self.json_type(ty).fields;
+            let paths = |paths: Vec<&String>| serde_json::to_string(&paths);
+            row.push(paths(fields.added.iter().map(|f| &f.path).collect())?);
+            row.push(paths(fields.removed.iter().map(|f| &f.path).collect())?);
+            row.push(paths(fields.changed.iter().map(|f| &f.path).collect())?);
+            write_row(row)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the fields whose kinds differ between the schemas.
+fn field_changes(old: &Schema, new: &Schema) -> Vec<FieldChange> {
+    let kinds = |schema: &Schema| -> BTreeMap<String, Vec<Kind>> {
+        schema
+            .fields()
+            .into_iter()
+            .map(|f| (f.path, f.kinds.into_iter().map(|(k, _)| k).collect()))
+            .collect()
+    };
+    let old = kinds(old);
+    let new = kinds(new);
+    let paths: BTreeSet<_> = old.keys().chain(new.keys()).collect();
+    paths
+        .into_iter()
+        .filter_map(|path| {
+            let old = old.get(path).cloned().unwrap_or_default();
+            let new = new.get(path).cloned().unwrap_or_default();
+            (old != new).then(|| FieldChange {
+                path: path.clone(),
+                old,
+                new,
+            })
+        })
+        .collect()
+}
+
+fn kind_names(kinds: &[Kind]) -> String {
+    let names: Vec<_> = kinds.iter().map(|k| k.name()).collect();
+    names.join(", ")
+}
+
+/// Formats the change from `old` to `new` as an absolute and, if `old` is not 0, a relative
+/// change, e.g. `+12 (+3.50%)`.
+fn format_change(old: u64, new: u64) -> String {
+    let change = new as i128 - old as i128;
+    if change == 0 {
+        return "0".to_string();
+    }
+    match percent_change(old, new) {
+        Some(percent) => format!("{:+} ({:+.2}%)", change, percent),
+        None => format!("{:+}", change),
+    }
+}
+
+fn json_changes(old: Option<Counts>, new: Option<Counts>) -> JsonChanges {
+    let old_or_zero = old.unwrap_or_default();
+    let new_or_zero = new.unwrap_or_default();
+    JsonChanges {
+        old,
+        new,
+        objects_change: new_or_zero.objects as i128 - old_or_zero.objects as i128,
+        objects_change_percent: percent_change(old_or_zero.objects, new_or_zero.objects),
+        bytes_change: new_or_zero.bytes as i128 - old_or_zero.bytes as i128,
+        bytes_change_percent: percent_change(old_or_zero.bytes, new_or_zero.bytes),
+    }
+}
+
+#[derive(Serialize)]
+struct JsonDiff<'a> {
+    schema_version: u32,
+    key_fields: &'a [String],
+    types: Vec<JsonTypeDiff>,
+    totals: JsonChanges,
+}
+
+#[derive(Serialize)]
+struct JsonTypeDiff {
+    key: Value,
+    status: Status,
+    #[serde(flatten)]
+    changes: JsonChanges,
+    fields: JsonFieldChanges,
+}
+
+#[derive(Serialize)]
+struct JsonChanges {
+    /// `None` if the type does not occur in the old input.
+    old: Option<Counts>,
+    /// `None` if the type does not occur in the new input.
+    new: Option<Counts>,
+    objects_change: i128,
+    /// The relative change in percent. `None` if there were no objects before.
+    objects_change_percent: Option<f64>,
+    bytes_change: i128,
+    /// The relative change in percent. `None` if there were no bytes before.
+    bytes_change_percent: Option<f64>,
+}
+
+#[derive(Default, Serialize)]
+struct JsonFieldChanges {
+    added: Vec<JsonField>,
+    removed: Vec<JsonField>,
+    /// Fields whose kinds changed.
+    changed: Vec<JsonKindChange>,
+}
+
+#[derive(Serialize)]
+struct JsonField {
+    path: String,
+    kinds: Vec<&'static str>,
+}
+
+#[derive(Serialize)]
+struct JsonKindChange {
+    path: String,
+    old_kinds: Vec<&'static str>,
+    new_kinds: Vec<&'static str>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::ErrorStats;
+    use crate::key::Key;
+    use crate::stats::{ByteMode, Stats};
+    use serde_json::json;
+
+    /// Creates a report of the types `(type, objects, bytes, entries)`.
+    fn report(types: &[(&str, u64, u64, &[Value])]) -> Report {
+        let mut stats = Stats::new(ErrorStats::new(0));
+        for &(ty, num, bytes, entries) in types {
+            let mut data = TypeData {
+                num,
+                bytes,
+                ..Default::default()
+            };
+            for entry in entries {
+                data.schema.add(entry);
+            }
+            stats.types.insert(vec![Some(Key(json!(ty)))], data);
+        }
+        Report::new(
+            vec!["type".to_string()],
+            false,
+            None,
+            None,
+            ByteMode::Content,
+            false,
+            &stats,
+        )
+    }
+
+    fn output(diff: &Diff, format: Format) -> String {
+        let mut out = vec![];
+        diff.write(format, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn thresholds(objects: Option<f64>, bytes: Option<f64>) -> Thresholds {
+        Thresholds {
+            max_objects_change: objects,
+            max_bytes_change: bytes,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn statuses() {
+        let old = report(&[("a", 1, 10, &[]), ("b", 2, 20, &[]), ("c", 3, 30, &[])]);
+        let new = report(&[("a", 1, 10, &[]), ("b", 2, 21, &[]), ("d", 4, 40, &[])]);
+        let diff = Diff::new(&old, &new);
+        let json: Value = serde_json::from_str(&output(&diff, Format::Json)).unwrap();
+        let statuses: Vec<_> = json["types"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|ty| (ty["key"]["type"].clone(), ty["status"].clone()))
+            .collect();
+        assert_eq!(
+            statuses,
+            [
+                (json!("a"), json!("unchanged")),
+                (json!("b"), json!("changed")),
+                (json!("c"), json!("removed")),
+                (json!("d"), json!("added")),
+            ]
+        );
+        assert_eq!(json["types"][2]["new"], Value::Null);
+        assert_eq!(json["types"][3]["old"], Value::Null);
+        assert_eq!(json["totals"]["old"], json!({"objects": 6, "bytes": 60}));
+        assert_eq!(json["totals"]["new"], json!({"objects": 7, "bytes": 71}));
+        assert!(diff.violations(&Thresholds::default()).is_empty());
+        let thresholds = Thresholds {
+            fail_on_added_types: true,
+            fail_on_removed_types: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            diff.violations(&thresholds),
+            [
+                "The type `type: \"c\"` was removed",
+                "The type `type: \"d\"` was added",
+            ]
+        );
+    }
+
+    #[test]
+    fn thresholds_are_percentages_of_the_old_counts() {
+        let old = report(&[("a", 100, 1000, &[])]);
+        let new = report(&[("a", 90, 1500, &[])]);
+        let diff = Diff::new(&old, &new);
+        assert!(diff
+            .violations(&thresholds(Some(10.0), Some(50.0)))
+            .is_empty());
+        assert_eq!(
+            diff.violations(&thresholds(Some(9.5), Some(49.5))),
+            [
+                "The number of objects of the type `type: \"a\"` changed by -10.00% (maximum 9.5%)",
+                "The number of bytes of the type `type: \"a\"` changed by +50.00% (maximum 49.5%)",
+            ]
+        );
+        // NOTE: Types that are not in both inputs are only checked by the other thresholds.
+        let diff = Diff::new(&old, &report(&[]));
+        assert!(diff
+            .violations(&thresholds(Some(0.0), Some(0.0)))
+            .is_empty());
+    }
+
+    #[test]
+    fn changes_from_zero_exceed_every_threshold() {
+        let old = report(&[("a", 0, 0, &[]), ("b", 0, 0, &[])]);
+        let new = report(&[("a", 3, 30, &[]), ("b", 0, 0, &[])]);
+        let diff = Diff::new(&old, &new);
+        assert_eq!(
+            diff.violations(&thresholds(Some(1e9), Some(1e9))),
+            [
+                "The number of objects of the type `type: \"a\"` changed from 0 to 3 (maximum 1000000000%)",
+                "The number of bytes of the type `type: \"a\"` changed from 0 to 30 (maximum 1000000000%)",
+            ]
+        );
+        let json: Value = serde_json::from_str(&output(&diff, Format::Json)).unwrap();
+        assert_eq!(json["types"][0]["objects_change"], json!(3));
+        assert_eq!(json["types"][0]["objects_change_percent"], Value::Null);
+        assert_eq!(json["types"][1]["status"], json!("unchanged"));
+    }
+
+    #[test]
+    fn field_changes() {
+        let old = report(&[("a", 2, 20, &[json!({"x": 1, "y": "s"}), json!({"x": 2})])]);
+        let new = report(&[("a", 2, 20, &[json!({"x": 1.5, "z": true}), json!({"x": 2})])]);
+        let diff = Diff::new(&old, &new);
+        let json: Value = serde_json::from_str(&output(&diff, Format::Json)).unwrap();
+        assert_eq!(json["types"][0]["status"], json!("changed"));
+        assert_eq!(
+            json["types"][0]["fields"],
+            json!({
+                "added": [{"path": "/z", "kinds": ["boolean"]}],
+                "removed": [{"path": "/y", "kinds": ["string"]}],
+                "changed": [{
+                    "path": "/x",
+                    "old_kinds": ["integer"],
+                    "new_kinds": ["integer", "number"],
+                }],
+            })
+        );
+        assert!(diff
+            .violations(&thresholds(Some(0.0), Some(0.0)))
+            .is_empty());
+        let thresholds = Thresholds {
+            fail_on_field_changes: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            diff.violations(&thresholds),
+            ["The fields of the type `type: \"a\"` changed"]
+        );
+    }
+
+    #[test]
+    fn separated() {
+        let old = report(&[("a", 2, 20, &[json!({"x": 1})]), ("b", 1, 10, &[])]);
+        let new = report(&[("a", 3, 20, &[json!({"y": 1})]), ("c", 1, 10, &[])]);
+        let diff = Diff::new(&old, &new);
+        let out = output(&diff, Format::Csv);
+        let mut lines = out.lines();
+        assert_eq!(
+            lines.next().unwrap(),
+            "schema_version,type,status,old_objects,new_objects,objects_change,\
+             objects_change_percent,old_bytes,new_bytes,bytes_change,bytes_change_percent,\
+             added_fields,removed_fields,changed_fields"
+        );
+        assert_eq!(
+            lines.next().unwrap(),
+            r#"1,"""a""",changed,2,3,1,50,20,20,0,0,"[""/y""]","[""/x""]",[]"#
+        );
+        assert_eq!(
+            lines.next().unwrap(),
+            r#"1,"""b""",removed,1,,-1,-100,10,,-10,-100,[],[],[]"#
+        );
+        assert_eq!(
+            lines.next().unwrap(),
+            r#"1,"""c""",added,,1,1,,,10,10,,[],[],[]"#
+        );
+        assert_eq!(lines.next(), None);
+    }
+}
@@ -0,0 +1,382 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{Number, Value};
+use std::collections::HashSet;
+
+/// The number of bits of the hash used to select a register of the HyperLogLog sketch.
+const PRECISION: u32 = 12;
+
+/// The number of registers of the HyperLogLog sketch.
+const REGISTERS: usize = 1 << PRECISION;
+
+/// The number of distinct hashes that are stored exactly before switching to a sketch.
+const MAX_EXACT: usize = 512;
+
+/// Counts the distinct values of a field.
+///
+/// The hashes of the values are stored exactly until there are more than `MAX_EXACT` of them.
+/// Then they are replaced by a HyperLogLog sketch with a standard error of about 1.6%, unless
+/// exact counting has been requested. Values are identified by a 64-bit hash, so even exact counts
+/// can be off if two values collide, which is very unlikely.
+///
+/// The state only depends on the set of values added, not on the order in which values were added
+/// or counters were merged.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct DistinctCounter {
+    /// Whether the hashes are never replaced by a sketch.
+    exact: bool,
+    repr: Repr,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+enum Repr {
+    Exact(#[serde(serialize_with = "serialize_sorted")] HashSet<u64>),
+    /// The maximum rank observed in each register.
+    Sketch(#[serde(deserialize_with = "deserialize_registers")] Box<[u8]>),
+}
+
+impl Default for Repr {
+    fn default() -> Self {
+        Repr::Exact(HashSet::new())
+    }
+}
+
+impl DistinctCounter {
+    /// Adds a value identified by its hash (see [`hash_value`]). If `exact` is true, the counter
+    /// never switches to a sketch. This must be the same for all values.
+    pub fn add(&mut self, hash: u64, exact: bool) {
+        self.exact = exact;
+        match &mut self.repr {
+            Repr::Exact(hashes) => {
+                hashes.insert(hash);
+                self.shrink();
+            }
+            Repr::Sketch(registers) => add_to_sketch(registers, hash),
+        }
+    }
+
+    pub fn merge(&mut self, other: &DistinctCounter) {
+        self.exact |= other.exact;
+        match (&mut self.repr, &other.repr) {
+            (Repr::Exact(l), Repr::Exact(r)) => l.extend(r),
+            (Repr::Sketch(l), Repr::Exact(r)) => {
+                for &hash in r {
+                    add_to_sketch(l, hash);
+                }
+            }
+            (Repr::Sketch(l), Repr::Sketch(r)) => {
+                for (l, r) in l.iter_mut().zip(r.iter()) {
+                    *l = (*l).max(*r);
+                }
+            }
+            (Repr::Exact(l), Repr::Sketch(r)) => {
+                let mut registers = r.clone();
+                for &hash in l.iter() {
+                    add_to_sketch(&mut registers, hash);
+                }
+                self.repr = Repr::Sketch(registers);
+            }
+        }
+        self.shrink();
+    }
+
+    /// Replaces the exact hashes by a sketch if there are too many of them.
+    fn shrink(&mut self) {
+        if let Repr::Exact(hashes) = &self.repr {
+            if !self.exact && hashes.len() > MAX_EXACT {
+                let mut registers = vec![0; REGISTERS].into_boxed_slice();
+                for &hash in hashes {
+                    add_to_sketch(&mut registers, hash);
+                }
+                self.repr = Repr::Sketch(registers);
+            }
+        }
+    }
+
+    /// Returns the (estimated) number of distinct values.
+    pub fn count(&self) -> u64 {
+        let registers = match &self.repr {
+            Repr::Exact(hashes) => return hashes.len() as u64,
+            Repr::Sketch(registers) => registers,
+        };
+        let m = REGISTERS as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum: f64 = registers.iter().map(|&r| 2f64.powi(-(r as i32))).sum();
+        let estimate = alpha * m * m / sum;
+        let zeros = registers.iter().filter(|&&r| r == 0).count();
+        // NOTE: For small cardinalities, linear counting is more accurate (Flajolet et al.,
+        // 2007).
+        if estimate <= 2.5 * m && zeros > 0 {
+            return (m * (m / zeros as f64).ln()).round() as u64;
+        }
+        estimate.round() as u64
+    }
+}
+
+/// Serializes the hashes in ascending order so that equal sets are serialized identically.
+fn serialize_sorted<S: Serializer>(
+    hashes: &HashSet<u64>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let mut hashes: Vec<_> = hashes.iter().collect();
+    hashes.sort_unstable();
+    hashes.serialize(serializer)
+}
+
+fn deserialize_registers<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Box<[u8]>, D::Error> {
+    let registers = Box::<[u8]>::deserialize(deserializer)?;
+    if registers.len() != REGISTERS {
+        return Err(serde::de::Error::invalid_length(
+            registers.len(),
+            &"one entry per register",
+        ));
+    }
+    Ok(registers)
+}
+
+fn add_to_sketch(registers: &mut [u8], hash: u64) {
+    let index = (hash >> (u64::BITS - PRECISION)) as usize;
+    let rest = hash << PRECISION;
+    let rank = (rest.leading_zeros() + 1).min(u64::BITS - PRECISION + 1) as u8;
+    registers[index] = registers[index].max(rank);
+}
+
+/// A 64-bit FNV-1a hasher whose output does not depend on the platform or the Rust version.
+struct Hasher(u64);
+
+impl Hasher {
+    fn new() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 ^= b as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn write_u64(&mut self, n: u64) {
+        self.write(&n.to_le_bytes());
+    }
+
+    fn write_str(&mut self, s: &[u8]) {
+        self.write_u64(s.len() as u64);
+        self.write(s);
+    }
+
+    /// Returns the hash. FNV-1a distributes the high bits poorly, so the state is mixed with the
+    /// finalizer of splitmix64.
+    fn finish(&self) -> u64 {
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+}
+
+/// Hashes arbitrary bytes.
+pub fn hash_bytes(bytes: &[u8]) -> u64 {
+    let mut hasher = Hasher::new();
+    hasher.write(bytes);
+    hasher.finish()
+}
+
+/// Hashes a json value. Two values have the same hash if they are equal.
+pub fn hash_value(value: &Value) -> u64 {
+    let mut hasher = Hasher::new();
+    write_value(&mut hasher, value);
+    hasher.finish()
+}
+
+/// Hashes the raw json form of a value. This is the same as [`hash_value`] but avoids parsing
+/// strings without escape sequences. Returns `None` if the value cannot be parsed.
+pub fn hash_raw(raw: &[u8]) -> Option<u64> {
+    if let [b'"', s @ .., b'"'] = raw {
+        if !s.contains(&b'\\') {
+            let mut hasher = Hasher::new();
+            hasher.write(&[3]);
+            hasher.write_str(s);
+            return Some(hasher.finish());
+        }
+    }
+    serde_json::from_slice(raw).ok().map(|v| hash_value(&v))
+}
+
+fn write_value(hasher: &mut Hasher, value: &Value) {
+    match value {
+        Value::Null => hasher.write(&[0]),
+        Value::Bool(b) => hasher.write(&[1, *b as u8]),
+        Value::Number(n) => {
+            hasher.write(&[2]);
+            write_number(hasher, n);
+        }
+        Value::String(s) => {
+            hasher.write(&[3]);
+            hasher.write_str(s.as_bytes());
+        }
+        Value::Array(a) => {
+            hasher.write(&[4]);
+            hasher.write_u64(a.len() as u64);
+            for v in a {
+                write_value(hasher, v);
+            }
+        }
+        Value::Object(o) => {
+            hasher.write(&[5]);
+            hasher.write_u64(o.len() as u64);
+            for (k, v) in o {
+                hasher.write_str(k.as_bytes());
+                write_value(hasher, v);
+            }
+        }
+    }
+}
+
+/// Hashes a number consistently with the `PartialEq` implementation of serde_json, which
+/// considers integers and floats to be different.
+fn write_number(hasher: &mut Hasher, n: &Number) {
+    if let Some(u) = n.as_u64() {
+        hasher.write(&[0]);
+        hasher.write_u64(u);
+    } else if let Some(i) = n.as_i64() {
+        hasher.write(&[1]);
+        hasher.write_u64(i as u64);
+    } else if let Some(f) = n.as_f64() {
+        hasher.write(&[2]);
+        hasher.write_u64((f + 0.0).to_bits());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counter(values: impl IntoIterator<Item = u64>, exact: bool) -> DistinctCounter {
+        let mut counter = DistinctCounter::default();
+        for v in values {
+            counter.add(hash_value(&Value::from(v)), exact);
+        }
+        counter
+    }
+
+    fn serialized(counter: &DistinctCounter) -> String {
+        serde_json::to_string(counter).unwrap()
+    }
+
+    #[test]
+    fn exact_until_the_limit() {
+        let counter = counter((0..MAX_EXACT as u64).chain(0..100), false);
+        assert!(matches!(counter.repr, Repr::Exact(_)));
+        assert_eq!(counter.count(), MAX_EXACT as u64);
+        let counter = self::counter(0..=MAX_EXACT as u64, false);
+        assert!(matches!(counter.repr, Repr::Sketch(_)));
+    }
+
+    #[test]
+    fn exact_counting_never_switches() {
+        let counter = counter(0..10_000, true);
+        assert!(matches!(counter.repr, Repr::Exact(_)));
+        assert_eq!(counter.count(), 10_000);
+    }
+
+    #[test]
+    fn sketch_accuracy() {
+        for n in [1_000u64, 10_000, 100_000, 1_000_000] {
+            let count = counter(0..n, false).count() as f64;
+            let error = (count - n as f64).abs() / n as f64;
+            // NOTE: The standard error is about 1.6%, so this fails with negligible probability
+            // for a good hash.
+            assert!(error < 0.05, "{} instead of {}", count, n);
+        }
+    }
+
+    #[test]
+    fn merge_does_not_depend_on_order() {
+        let expected = counter(0..5_000, false);
+        let parts = [
+            counter(0..100, false),
+            counter(50..300, false),
+            counter(300..2_000, false),
+            counter(1_000..5_000, false),
+            DistinctCounter::default(),
+        ];
+        for order in [
+            [0, 1, 2, 3, 4],
+            [4, 3, 2, 1, 0],
+            [1, 3, 0, 4, 2],
+            [2, 0, 4, 1, 3],
+        ] {
+            let mut merged = DistinctCounter::default();
+            for i in order {
+                merged.merge(&parts[i]);
+            }
+            assert_eq!(serialized(&merged), serialized(&expected), "{:?}", order);
+        }
+    }
+
+    #[test]
+    fn merge_switches_to_a_sketch() {
+        let mut merged = counter(0..300, false);
+        merged.merge(&counter(300..600, false));
+        assert!(matches!(merged.repr, Repr::Sketch(_)));
+        assert_eq!(serialized(&merged), serialized(&counter(0..600, false)));
+        let mut merged = counter(0..300, true);
+        merged.merge(&counter(300..600, true));
+        assert_eq!(merged.count(), 600);
+    }
+
+    #[test]
+    fn serialization_round_trip() {
+        for counter in [counter(0..100, false), counter(0..10_000, false)] {
+            let json = serialized(&counter);
+            let deserialized: DistinctCounter = serde_json::from_str(&json).unwrap();
+            assert_eq!(serialized(&deserialized), json);
+            assert_eq!(deserialized.count(), counter.count());
+        }
+    }
+
+    #[test]
+    fn registers_are_checked() {
+        let registers = vec![0u8; REGISTERS - 1];
+        let json = serde_json::json!({"exact": false, "repr": {"Sketch": registers}});
+        let e = serde_json::from_value::<DistinctCounter>(json).unwrap_err();
+        assert!(e.to_string().contains("one entry per register"), "{}", e);
+    }
+
+    #[test]
+    fn hashes_respect_equality() {
+        let values = [
+            "null",
+            "true",
+            "0",
+            "0.0",
+            "-0.0",
+            "-1",
+            "1e3",
+            r#""""#,
+            r#""a""#,
+            r#""a""#,
+            "[]",
+            "[0]",
+            r#"{"a":1,"b":2}"#,
+            r#"{"b":2,"a":1}"#,
+        ];
+        for l in values {
+            let lv: Value = serde_json::from_str(l).unwrap();
+            assert_eq!(hash_raw(l.as_bytes()), Some(hash_value(&lv)), "{}", l);
+            for r in values {
+                let rv: Value = serde_json::from_str(r).unwrap();
+                assert_eq!(
+                    hash_value(&lv) == hash_value(&rv),
+                    lv == rv,
+                    "{} == {}",
+                    l,
+                    r
+                );
+            }
+        }
+        assert_eq!(hash_raw(b"\"a"), None);
+    }
+}
@@ -0,0 +1,29 @@
+use std::time::Duration;
+
+/// Parses a duration such as `500ms`, `30s`, `5m`, `1h`, or `1d`.
+///
+/// The number must be a non-negative integer. A number without a unit is interpreted as seconds.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("`{}` does not start with a number", s))?;
+    let millis = match unit {
+        "ms" => 1,
+        "" | "s" => 1000,
+        "m" => 60 * 1000,
+        "h" => 60 * 60 * 1000,
+        "d" => 24 * 60 * 60 * 1000,
+        _ => {
+            return Err(format!(
+                "Unknown unit `{}`. Valid units are ms, s, m, h, and d",
+                unit
+            ))
+        }
+    };
+    number
+        .checked_mul(millis)
+        .map(Duration::from_millis)
+        .ok_or_else(|| format!("`{}` is too large", s))
+}
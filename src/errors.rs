@@ -0,0 +1,151 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// What to do with lines that cannot be processed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum OnError {
+    /// Abort the analysis
+    Fail,
+    /// Skip the line and report a warning to the handler set with
+    /// [`AnalyzerBuilder::on_warning`]
+    ///
+    /// [`AnalyzerBuilder::on_warning`]: crate::AnalyzerBuilder::on_warning
+    Warn,
+    /// Skip the line silently
+    Skip,
+}
+
+/// How lines that are not valid UTF-8 are decoded.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum Utf8Mode {
+    /// Treat the line as an error
+    Strict,
+    /// Replace invalid bytes by U+FFFD
+    Lossy,
+    /// Escape invalid bytes in strings as `\u00XX` so that different values stay distinct and
+    /// replace the others by U+FFFD
+    Escape,
+}
+
+/// The reason why a line could not be processed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// The line is not valid UTF-8.
+    InvalidUtf8,
+    /// The line is not a valid json value.
+    InvalidJson,
+    /// The entry does not contain any of the key fields. Only reported if the key fields are
+    /// required.
+    MissingKey,
+    /// The timestamp field is missing or cannot be parsed.
+    InvalidTimestamp,
+}
+
+impl ErrorKind {
+    pub const ALL: [ErrorKind; 4] = [
+        ErrorKind::InvalidUtf8,
+        ErrorKind::InvalidJson,
+        ErrorKind::MissingKey,
+        ErrorKind::InvalidTimestamp,
+    ];
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            ErrorKind::InvalidUtf8 => "invalid UTF-8",
+            ErrorKind::InvalidJson => "invalid json",
+            ErrorKind::MissingKey => "missing key",
+            ErrorKind::InvalidTimestamp => "invalid timestamp",
+        };
+        f.write_str(s)
+    }
+}
+
+/// An error that affects only a single line.
+pub(crate) struct LineError {
+    pub kind: ErrorKind,
+    pub error: anyhow::Error,
+}
+
+impl LineError {
+    pub fn new(kind: ErrorKind, error: anyhow::Error) -> Self {
+        Self { kind, error }
+    }
+}
+
+/// The location of a line in the input.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct Location {
+    /// The name of the input file. `-` refers to stdin.
+    pub file: String,
+    /// The line number starting at 1.
+    pub line: u64,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)
+    }
+}
+
+/// Statistics about the lines that were skipped.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ErrorStats {
+    /// The maximum number of line numbers to remember per error kind.
+    max_samples: usize,
+    categories: [ErrorCategory; ErrorKind::ALL.len()],
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct ErrorCategory {
+    /// The number of lines with this error.
+    num: u64,
+    /// The locations of the first lines with this error.
+    samples: Vec<Location>,
+}
+
+impl ErrorStats {
+    pub(crate) fn new(max_samples: usize) -> Self {
+        Self {
+            max_samples,
+            categories: Default::default(),
+        }
+    }
+
+    pub(crate) fn record(&mut self, kind: ErrorKind, location: impl FnOnce() -> Location) {
+        let category = &mut self.categories[kind as usize];
+        category.num += 1;
+        if category.samples.len() < self.max_samples {
+            category.samples.push(location());
+        }
+    }
+
+    /// Adds the errors of `other`, which must have occurred after the errors in `self`.
+    pub(crate) fn merge(&mut self, other: ErrorStats) {
+        for (category, other) in self.categories.iter_mut().zip(other.categories) {
+            category.num += other.num;
+            let remaining = self.max_samples.saturating_sub(category.samples.len());
+            category
+                .samples
+                .extend(other.samples.into_iter().take(remaining));
+        }
+    }
+
+    /// The total number of lines that were skipped.
+    pub fn total(&self) -> u64 {
+        self.categories.iter().map(|c| c.num).sum()
+    }
+
+    /// Returns the number of lines with this error and the locations of the first such lines.
+    pub fn get(&self, kind: ErrorKind) -> (u64, &[Location]) {
+        let category = &self.categories[kind as usize];
+        (category.num, &category.samples)
+    }
+}
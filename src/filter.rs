@@ -0,0 +1,514 @@
+use crate::path::FieldPath;
+use regex::Regex;
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+/// A condition that entries must satisfy to be included in the report.
+///
+/// The syntax is
+///
+/// ```text
+/// expr       = and ("or" and)*
+/// and        = unary ("and" unary)*
+/// unary      = "not" unary | "(" expr ")" | "exists" FIELD | FIELD op VALUE | FIELD match STRING
+/// op         = "==" | "!=" | "<" | "<=" | ">" | ">="
+/// match      = "=~" | "!~"
+/// ```
+///
+/// where `FIELD` is a path as accepted by `--key` and `VALUE` is a json string, number, boolean,
+/// or null. Numbers are compared by their numeric value and strings lexicographically. Ordering
+/// comparisons between values of other kinds are false. Comparisons and matches involving a field
+/// that the entry does not contain are false except for `!=` and `!~`.
+#[derive(Clone, Debug)]
+pub struct Filter {
+    /// The expression as written.
+    source: String,
+    expr: Expr,
+    /// The fields referred to by the expression.
+    fields: Vec<FieldPath>,
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    /// The index of the field in `Filter::fields`.
+    Exists(usize),
+    Compare(usize, Op, Value),
+    Match(usize, Regex),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Filter {
+    /// The fields referred to by the filter.
+    pub fn fields(&self) -> &[FieldPath] {
+        &self.fields
+    }
+
+    /// Evaluates the filter. `value(i)` returns the value of the i-th field of
+    /// [`Filter::fields`] or `None` if the entry does not contain the field.
+    pub fn matches<'a>(&self, value: &impl Fn(usize) -> Option<&'a Value>) -> bool {
+        self.expr.eval(value)
+    }
+}
+
+/// Formats the filter as written.
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl Expr {
+    fn eval<'a>(&self, value: &impl Fn(usize) -> Option<&'a Value>) -> bool {
+        match self {
+            Expr::And(l, r) => l.eval(value) && r.eval(value),
+            Expr::Or(l, r) => l.eval(value) || r.eval(value),
+            Expr::Not(e) => !e.eval(value),
+            Expr::Exists(field) => value(*field).is_some(),
+            Expr::Compare(field, op, rhs) => {
+                let lhs = match value(*field) {
+                    Some(lhs) => lhs,
+                    None => return *op == Op::Ne,
+                };
+                match (op, compare(lhs, rhs)) {
+                    (Op::Eq, o) => o == Some(Ordering::Equal),
+                    (Op::Ne, o) => o != Some(Ordering::Equal),
+                    (Op::Lt, Some(o)) => o.is_lt(),
+                    (Op::Le, Some(o)) => o.is_le(),
+                    (Op::Gt, Some(o)) => o.is_gt(),
+                    (Op::Ge, Some(o)) => o.is_ge(),
+                    (_, None) => false,
+                }
+            }
+            Expr::Match(field, regex) => match value(*field) {
+                Some(Value::String(s)) => regex.is_match(s),
+                _ => false,
+            },
+        }
+    }
+}
+
+/// Compares two values. Returns `None` if the values are not comparable.
+///
+/// Numbers are compared by their value, strings lexicographically, and all other values only for
+/// equality.
+fn compare(l: &Value, r: &Value) -> Option<Ordering> {
+    match (l, r) {
+        (Value::Number(l), Value::Number(r)) => {
+            if let (Some(l), Some(r)) = (l.as_i64(), r.as_i64()) {
+                return Some(l.cmp(&r));
+            }
+            if let (Some(l), Some(r)) = (l.as_u64(), r.as_u64()) {
+                return Some(l.cmp(&r));
+            }
+            l.as_f64()?.partial_cmp(&r.as_f64()?)
+        }
+        (Value::String(l), Value::String(r)) => Some(l.cmp(r)),
+        _ if l == r => Some(Ordering::Equal),
+        _ => None,
+    }
+}
+
+/// An error in a filter expression.
+#[derive(Debug)]
+pub struct ParseError {
+    source: String,
+    /// The position of the error in characters starting at 0.
+    pos: usize,
+    message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} at column {}", self.message, self.pos + 1)?;
+        writeln!(f, "    {}", self.source)?;
+        write!(f, "    {:>1$}", "^", self.pos + 1)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    LParen,
+    RParen,
+    Op(Op),
+    /// `=~` or, if negated, `!~`.
+    Match {
+        negated: bool,
+    },
+    Value(Value),
+    /// A keyword or field path.
+    Word(String),
+}
+
+impl FromStr for Filter {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            source: s,
+            tokens: tokenize(s)?,
+            pos: 0,
+            fields: vec![],
+        };
+        let expr = parser.or()?;
+        if parser.pos < parser.tokens.len() {
+            return Err(parser.error("Expected `and`, `or`, or the end of the filter"));
+        }
+        Ok(Filter {
+            source: s.to_string(),
+            expr,
+            fields: parser.fields,
+        })
+    }
+}
+
+/// Splits the filter into tokens and their positions in characters.
+fn tokenize(s: &str) -> Result<Vec<(usize, Token)>, ParseError> {
+    let chars: Vec<char> = s.chars().collect();
+    let error = |pos, message: &str| ParseError {
+        source: s.to_string(),
+        pos,
+        message: message.to_string(),
+    };
+    let is_word_char = |c: char| !c.is_whitespace() && !"()=!<>\"".contains(c);
+    let mut tokens = vec![];
+    let mut pos = 0;
+    while pos < chars.len() {
+        let start = pos;
+        let next = chars.get(pos + 1).copied();
+        let token = match chars[pos] {
+            c if c.is_whitespace() => {
+                pos += 1;
+                continue;
+            }
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            '=' if next == Some('=') => Token::Op(Op::Eq),
+            '=' if next == Some('~') => Token::Match { negated: false },
+            '!' if next == Some('=') => Token::Op(Op::Ne),
+            '!' if next == Some('~') => Token::Match { negated: true },
+            '<' if next == Some('=') => Token::Op(Op::Le),
+            '>' if next == Some('=') => Token::Op(Op::Ge),
+            '<' => Token::Op(Op::Lt),
+            '>' => Token::Op(Op::Gt),
+            '"' => {
+                pos += 1;
+                while pos < chars.len() && chars[pos] != '"' {
+                    pos += if chars[pos] == '\\' { 2 } else { 1 };
+                }
+                if pos >= chars.len() {
+                    return Err(error(start, "Unterminated string"));
+                }
+                let literal: String = chars[start..=pos].iter().collect();
+                match serde_json::from_str(&literal) {
+                    Ok(v) => Token::Value(v),
+                    Err(e) => return Err(error(start, &format!("Invalid string: {}", e))),
+                }
+            }
+            c if is_word_char(c) => {
+                while pos + 1 < chars.len() && is_word_char(chars[pos + 1]) {
+                    pos += 1;
+                }
+                let word: String = chars[start..=pos].iter().collect();
+                match word.as_str() {
+                    "true" | "false" | "null" => Token::Value(serde_json::from_str(&word).unwrap()),
+                    _ if word.starts_with(|c: char| c == '-' || c.is_ascii_digit()) => {
+                        match serde_json::from_str::<serde_json::Number>(&word) {
+                            Ok(n) => Token::Value(Value::Number(n)),
+                            Err(_) => return Err(error(start, "Invalid number")),
+                        }
+                    }
+                    _ => Token::Word(word),
+                }
+            }
+            _ => return Err(error(start, "Unexpected character")),
+        };
+        pos += match token {
+            Token::Op(Op::Lt | Op::Gt) | Token::LParen | Token::RParen => 1,
+            Token::Op(_) | Token::Match { .. } => 2,
+            _ => 1,
+        };
+        tokens.push((start, token));
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    tokens: Vec<(usize, Token)>,
+    /// The index of the next token.
+    pos: usize,
+    fields: Vec<FieldPath>,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> ParseError {
+        let pos = match self.tokens.get(self.pos) {
+            Some((pos, _)) => *pos,
+            None => self.source.chars().count(),
+        };
+        ParseError {
+            source: self.source.to_string(),
+            pos,
+            message: message.to_string(),
+        }
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, t)| t)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Word(w)) if w == keyword => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.and()?;
+        while self.keyword("or") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.unary()?;
+        while self.keyword("and") {
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        if self.keyword("not") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.keyword("exists") {
+            return Ok(Expr::Exists(self.field()?));
+        }
+        if self.peek() == Some(&Token::LParen) {
+            self.pos += 1;
+            let expr = self.or()?;
+            if self.peek() != Some(&Token::RParen) {
+                return Err(self.error("Expected `)`"));
+            }
+            self.pos += 1;
+            return Ok(expr);
+        }
+        let field = self.field()?;
+        let op_pos = self.pos;
+        match self.next() {
+            Some(Token::Op(op)) => match self.next() {
+                Some(Token::Value(value)) => Ok(Expr::Compare(field, op, value)),
+                _ => {
+                    self.pos -= 1;
+                    Err(self.error("Expected a json value"))
+                }
+            },
+            Some(Token::Match { negated }) => {
+                let regex = match self.next() {
+                    Some(Token::Value(Value::String(s))) => Regex::new(&s).map_err(|e| {
+                        // NOTE: Syntax errors span multiple lines, the last of which describes the
+                        // error.
+                        let e = e.to_string();
+                        let e = e.lines().last().unwrap_or_default();
+                        let e = e.strip_prefix("error: ").unwrap_or(e);
+                        self.pos -= 1;
+                        self.error(&format!("Invalid regular expression: {}", e))
+                    })?,
+                    _ => {
+                        self.pos -= 1;
+                        return Err(self.error("Expected a string containing a regular expression"));
+                    }
+                };
+                let expr = Expr::Match(field, regex);
+                Ok(match negated {
+                    true => Expr::Not(Box::new(expr)),
+                    false => expr,
+                })
+            }
+            _ => {
+                self.pos = op_pos;
+                Err(self.error("Expected a comparison operator or `=~`"))
+            }
+        }
+    }
+
+    /// Parses a field path and returns its index in `fields`.
+    fn field(&mut self) -> Result<usize, ParseError> {
+        let path = match self.peek() {
+            Some(Token::Word(w)) if !["and", "or", "not", "exists"].contains(&w.as_str()) => {
+                w.parse::<FieldPath>().map_err(|e| self.error(&e))?
+            }
+            _ => return Err(self.error("Expected a field")),
+        };
+        self.pos += 1;
+        let idx = match self.fields.iter().position(|f| *f == path) {
+            Some(idx) => idx,
+            None => {
+                self.fields.push(path);
+                self.fields.len() - 1
+            }
+        };
+        Ok(idx)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOC: &str = r#"{"type":"click","status":404,"latency":1.5,"ok":false,"user":{"name":"a b"},
+        "tags":["x"],"big":18446744073709551615,"neg":-3,"empty":null}"#;
+
+    fn eval(filter: &str) -> bool {
+        let filter: Filter = filter.parse().unwrap_or_else(|e| panic!("{}", e));
+        let doc: Value = serde_json::from_str(DOC).unwrap();
+        filter.matches(&|i| filter.fields()[i].lookup(&doc))
+    }
+
+    /// Returns the message and the column of the error.
+    fn error(filter: &str) -> (String, usize) {
+        let e = filter.parse::<Filter>().unwrap_err();
+        (e.message, e.pos + 1)
+    }
+
+    #[test]
+    fn comparisons() {
+        assert!(eval(r#"type == "click""#));
+        assert!(eval(r#"type != "view""#));
+        assert!(eval("status >= 400 and status < 500"));
+        assert!(eval("status > 403.5"));
+        assert!(eval("latency <= 1.5 and latency > 1"));
+        assert!(eval("neg < 0 and neg == -3"));
+        assert!(eval("big > 9223372036854775807"));
+        assert!(eval("big > -1"));
+        assert!(eval("ok == false and empty == null"));
+        assert!(eval(r#"type < "view" and type > "a""#));
+        assert!(eval(r#"user.name == "a b""#));
+        assert!(eval(r#"/tags/0 == "x""#));
+        // NOTE: Ordering comparisons between values of different kinds are false.
+        assert!(!eval(r#"status < "5""#));
+        assert!(!eval(r#"status >= "5""#));
+        assert!(!eval("ok < true"));
+        assert!(eval(r#"status != "404""#));
+    }
+
+    #[test]
+    fn missing_fields() {
+        assert!(!eval("missing == 1"));
+        assert!(eval("missing != 1"));
+        assert!(!eval("missing < 1"));
+        assert!(!eval("missing >= 1"));
+        assert!(!eval(r#"missing =~ "x""#));
+        assert!(eval(r#"missing !~ "x""#));
+        assert!(!eval("exists missing"));
+        assert!(eval("exists empty"));
+        assert!(eval("not exists user.missing"));
+    }
+
+    #[test]
+    fn matches() {
+        assert!(eval(r#"type =~ "^cl""#));
+        assert!(!eval(r#"type !~ "^cl""#));
+        assert!(eval(r#"user.name =~ "\\s""#));
+        // NOTE: Only strings can match.
+        assert!(!eval(r#"status =~ "4""#));
+        assert!(eval(r#"status !~ "4""#));
+    }
+
+    #[test]
+    fn precedence() {
+        assert!(eval("status == 1 and ok == true or neg == -3"));
+        assert!(!eval("status == 1 and (ok == true or neg == -3)"));
+        assert!(eval("neg == -3 or status == 1 and ok == true"));
+        assert!(eval("not status == 1 and not ok == true"));
+        assert!(!eval("not (status == 404 or ok == true)"));
+        assert!(eval("not not exists status"));
+        assert!(eval("((status==404))and(neg<0)"));
+    }
+
+    #[test]
+    fn fields_are_shared() {
+        let filter: Filter = "status > 1 and status < 1000 or type == 1 or exists status"
+            .parse()
+            .unwrap();
+        assert_eq!(filter.fields().len(), 2);
+    }
+
+    #[test]
+    fn errors() {
+        let expected = |message: &str, column| (message.to_string(), column);
+        assert_eq!(error(""), expected("Expected a field", 1));
+        assert_eq!(
+            error("status"),
+            expected("Expected a comparison operator or `=~`", 7)
+        );
+        assert_eq!(error("status =="), expected("Expected a json value", 10));
+        assert_eq!(error("status == x"), expected("Expected a json value", 11));
+        assert_eq!(
+            error("status == 1 status"),
+            expected("Expected `and`, `or`, or the end of the filter", 13)
+        );
+        assert_eq!(error("(status == 1"), expected("Expected `)`", 13));
+        assert_eq!(error("status == 1 and"), expected("Expected a field", 16));
+        assert_eq!(error("and == 1"), expected("Expected a field", 1));
+        assert_eq!(error("exists"), expected("Expected a field", 7));
+        assert_eq!(error("status = 1"), expected("Unexpected character", 8));
+        assert_eq!(
+            error(r#"type == "click"#),
+            expected("Unterminated string", 9)
+        );
+        assert_eq!(error("status == 1x"), expected("Invalid number", 11));
+        assert_eq!(
+            error("type =~ 1"),
+            expected("Expected a string containing a regular expression", 9)
+        );
+        let (message, column) = error(r#"type =~ "(""#);
+        assert!(
+            message.starts_with("Invalid regular expression: "),
+            "{}",
+            message
+        );
+        assert_eq!(column, 9);
+        let (message, column) = error(r#"type == "\x""#);
+        assert!(message.starts_with("Invalid string: "), "{}", message);
+        assert_eq!(column, 9);
+    }
+
+    #[test]
+    fn error_positions_count_characters() {
+        let e = r#"type == "é" é"#.parse::<Filter>().unwrap_err();
+        assert_eq!(e.pos, 12);
+        assert_eq!(
+            e.to_string(),
+            "Expected `and`, `or`, or the end of the filter at column 13\n    type == \"é\" é\n                ^"
+        );
+    }
+}
@@ -0,0 +1,362 @@
+use crate::analyzer::Analyzer;
+use crate::input::{file_id, is_compressed, read_header, Chunk, FileId, CHUNK_SIZE};
+use crate::process::{process_chunk, Worker};
+use anyhow::{bail, Context, Result};
+use std::borrow::Cow;
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::sync::atomic::{AtomicBool, Ordering::Relaxed};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often the file is checked for new data once the end of the file has been reached.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The number of bytes at the start of the file that are compared to detect that the file has
+/// been truncated and rewritten.
+const PREFIX_LEN: usize = 4096;
+
+/// Processes a file that is still being written to.
+///
+/// After the end of the file has been reached, the file is checked for new lines until `stop` is
+/// set. Then the file is processed up to its end. Incomplete lines are not processed until they
+/// have been completed or `stop` is set.
+///
+/// If the file is replaced (e.g. by log rotation), the new file is processed from the start. If
+/// the file is truncated, it is processed again from the start. Truncation is detected if the
+/// file becomes shorter or if its first bytes change, e.g. because it was truncated and then
+/// rewritten to its previous length between two checks.
+///
+/// Compressed files cannot be followed and are rejected.
+///
+/// `render` is called with the analyzer every `interval`.
+pub fn follow_file(
+    analyzer: &mut Analyzer,
+    file: &OsStr,
+    interval: Duration,
+    stop: &AtomicBool,
+    mut render: impl FnMut(&Analyzer) -> Result<()>,
+) -> Result<()> {
+    let name = file.to_string_lossy();
+    let mut worker = Worker::new(&analyzer.options, &analyzer.scanner, &name);
+
+    let mut tail = Tail::open(file)?;
+    let mut last_render = Instant::now();
+    loop {
+        let stopped = stop.load(Relaxed);
+        let eof = tail.read(CHUNK_SIZE)?;
+        // NOTE: Once the program is interrupted, the rest of the file is processed including an
+        // incomplete last line since it would also be processed if the file had not been
+        // followed.
+        let chunk = match stopped && eof {
+            true => tail.take_all(),
+            false => tail.take_complete_lines(),
+        };
+        if let Some(chunk) = chunk {
+            process(&mut worker, analyzer, &chunk)?;
+        }
+        if stopped {
+            match eof {
+                true => return Ok(()),
+                false => continue,
+            }
+        }
+        if last_render.elapsed() >= interval {
+            render(analyzer)?;
+            last_render = Instant::now();
+        }
+        if eof {
+            if let Some(chunk) = tail.check_rotation(file)? {
+                process(&mut worker, analyzer, &chunk)?;
+            }
+            thread::sleep(POLL_INTERVAL.min(interval));
+        }
+    }
+}
+
+fn process(worker: &mut Worker, analyzer: &mut Analyzer, chunk: &Chunk) -> Result<()> {
+    let (chunk_stats, warnings) = process_chunk(worker, chunk)?;
+    analyzer.options.warn(&warnings);
+    analyzer.stats.merge(chunk_stats);
+    Ok(())
+}
+
+/// A file that is read incrementally.
+struct Tail {
+    reader: BufReader<File>,
+    /// The identity of the open file.
+    id: Option<FileId>,
+    /// The number of bytes read from the open file.
+    offset: u64,
+    /// Data that has been read but not yet processed. Only the last line can be incomplete.
+    buf: Vec<u8>,
+    /// The number of the first line in `buf`.
+    next_line: u64,
+    /// The first `PREFIX_LEN` bytes read from the open file.
+    prefix: Vec<u8>,
+}
+
+impl Tail {
+    fn open(path: &OsStr) -> Result<Self> {
+        let file = File::open(path).context("Could not open the file")?;
+        let metadata = file.metadata().context("Could not stat the file")?;
+        let mut reader = BufReader::new(file);
+        let header = read_header(&mut reader).context("Could not read from the file")?;
+        // NOTE: A growing compressed stream cannot be decompressed incrementally.
+        if is_compressed(&header) {
+            bail!("Compressed files cannot be followed");
+        }
+        reader
+            .seek(SeekFrom::Start(0))
+            .context("Could not seek in the file")?;
+        Ok(Self {
+            reader,
+            id: file_id(&metadata),
+            offset: 0,
+            buf: vec![],
+            next_line: 1,
+            prefix: vec![],
+        })
+    }
+
+    /// Reads until the end of the file or until `limit` bytes are buffered. Returns whether the
+    /// end of the file has been reached.
+    fn read(&mut self, limit: usize) -> Result<bool> {
+        while self.buf.len() < limit {
+            let start = self.buf.len();
+            let n = self
+                .reader
+                .read_until(b'\n', &mut self.buf)
+                .context("Could not read from the file")?;
+            self.offset += n as u64;
+            let missing = PREFIX_LEN.saturating_sub(self.prefix.len()).min(n);
+            self.prefix
+                .extend_from_slice(&self.buf[start..start + missing]);
+            if n == 0 || self.buf.last() != Some(&b'\n') {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Removes all complete lines from the buffer.
+    fn take_complete_lines(&mut self) -> Option<Chunk<'static>> {
+        let len = memchr::memrchr(b'\n', &self.buf)? + 1;
+        let rest = self.buf.split_off(len);
+        let data = std::mem::replace(&mut self.buf, rest);
+        Some(self.chunk(data))
+    }
+
+    /// Removes all lines, including an incomplete last line, from the buffer.
+    fn take_all(&mut self) -> Option<Chunk<'static>> {
+        if self.buf.is_empty() {
+            return None;
+        }
+        let data = std::mem::take(&mut self.buf);
+        Some(self.chunk(data))
+    }
+
+    fn chunk(&mut self, data: Vec<u8>) -> Chunk<'static> {
+        let first_line = self.next_line;
+        self.next_line += memchr::memchr_iter(b'\n', &data).count() as u64;
+        Chunk {
+            data: Cow::Owned(data),
+            first_line,
+        }
+    }
+
+    /// Handles log rotation and truncation. Must only be called at the end of the file.
+    ///
+    /// Returns the rest of the old file if the file has been replaced.
+    fn check_rotation(&mut self, path: &OsStr) -> Result<Option<Chunk<'static>>> {
+        // NOTE: During log rotation, the path might briefly not exist.
+        let metadata = match std::fs::metadata(path) {
+            Ok(m) => m,
+            Err(_) => return Ok(None),
+        };
+        if file_id(&metadata) != self.id {
+            // NOTE: Lines might have been appended to the old file after the end of the file was
+            // reached and before it was replaced. The last line of the old file will never be
+            // completed.
+            self.read(usize::MAX)?;
+            let rest = self.take_all();
+            *self = Self::open(path)?;
+            return Ok(rest);
+        }
+        if metadata.len() < self.offset || self.prefix_changed()? {
+            self.reader
+                .seek(SeekFrom::Start(0))
+                .context("Could not seek in the file")?;
+            self.offset = 0;
+            self.buf.clear();
+            self.next_line = 1;
+            self.prefix.clear();
+        }
+        Ok(None)
+    }
+
+    /// Returns whether the first bytes of the open file differ from those that were read. Must
+    /// only be called at the end of the file.
+    fn prefix_changed(&mut self) -> Result<bool> {
+        self.reader
+            .seek(SeekFrom::Start(0))
+            .context("Could not seek in the file")?;
+        let mut prefix = Vec::with_capacity(self.prefix.len());
+        (&mut self.reader)
+            .take(self.prefix.len() as u64)
+            .read_to_end(&mut prefix)
+            .context("Could not read from the file")?;
+        self.reader
+            .seek(SeekFrom::Start(self.offset))
+            .context("Could not seek in the file")?;
+        Ok(prefix != self.prefix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key::display_component;
+    use crate::test_util::TempFile;
+
+    /// The number of objects of each type.
+    fn objects(analyzer: &Analyzer) -> Vec<(String, u64)> {
+        let report = analyzer.report();
+        let types = report.types().iter();
+        types
+            .map(|(key, data)| (display_component(&key[0]), data.objects()))
+            .collect()
+    }
+
+    fn total(analyzer: &Analyzer) -> u64 {
+        analyzer.report().total().objects()
+    }
+
+    /// Follows a file and performs the steps one after another. A step is performed once the
+    /// previous steps have been processed, i.e. once the total number of objects is the one
+    /// expected by the step. `stop` is set after the last step.
+    fn follow<'a>(file: &TempFile, steps: Vec<(u64, Box<dyn FnOnce() + 'a>)>) -> Analyzer {
+        let mut analyzer = Analyzer::builder().build().unwrap();
+        let stop = AtomicBool::new(false);
+        let mut steps = steps.into_iter().peekable();
+        let start = Instant::now();
+        let render = |analyzer: &Analyzer| {
+            assert!(
+                start.elapsed() < Duration::from_secs(10),
+                "{:?}",
+                objects(analyzer)
+            );
+            if let Some((expected, _)) = steps.peek() {
+                if total(analyzer) == *expected {
+                    let (_, step) = steps.next().unwrap();
+                    step();
+                    if steps.peek().is_none() {
+                        stop.store(true, Relaxed);
+                    }
+                }
+            }
+            Ok(())
+        };
+        let interval = Duration::from_millis(1);
+        follow_file(&mut analyzer, file.0.as_os_str(), interval, &stop, render).unwrap();
+        analyzer
+    }
+
+    fn line(ty: &str) -> Vec<u8> {
+        format!("{{\"type\":\"{}\"}}\n", ty).into_bytes()
+    }
+
+    #[test]
+    fn appended_lines() {
+        let file = TempFile::new("follow-appended", &[line("a"), line("a")].concat());
+        let analyzer = follow(
+            &file,
+            vec![
+                (2, Box::new(|| file.append(b"{\"type\":\"b\"}\n{\"type\":"))),
+                // NOTE: The incomplete line is not processed until it has been completed.
+                (3, Box::new(|| file.append(b"\"b\"}\n"))),
+                (4, Box::new(|| file.append(b"{\"type\":\"c\"}"))),
+            ],
+        );
+        // NOTE: The incomplete last line is processed once following stops.
+        let expected = [("\"a\"", 2), ("\"b\"", 2), ("\"c\"", 1)];
+        let expected: Vec<_> = expected.iter().map(|&(k, n)| (k.to_string(), n)).collect();
+        assert_eq!(objects(&analyzer), expected);
+    }
+
+    #[test]
+    fn truncated_file() {
+        let file = TempFile::new(
+            "follow-truncated",
+            &[line("a"), line("a"), line("a")].concat(),
+        );
+        let analyzer = follow(&file, vec![(3, Box::new(|| file.overwrite(&line("b"))))]);
+        let expected = vec![("\"a\"".to_string(), 3), ("\"b\"".to_string(), 1)];
+        assert_eq!(objects(&analyzer), expected);
+    }
+
+    #[test]
+    fn truncated_and_rewritten_file() {
+        let file = TempFile::new(
+            "follow-rewritten",
+            &[line("a"), line("a"), line("a")].concat(),
+        );
+        // NOTE: The file is not shorter than before when the truncation is noticed.
+        let rewrite = || file.overwrite(&[line("b"), line("b"), line("b"), line("b")].concat());
+        let analyzer = follow(&file, vec![(3, Box::new(rewrite))]);
+        let expected = vec![("\"a\"".to_string(), 3), ("\"b\"".to_string(), 4)];
+        assert_eq!(objects(&analyzer), expected);
+    }
+
+    #[test]
+    fn rotated_file() {
+        let file = TempFile::new("follow-rotated", &line("a"));
+        let rotated = TempFile::new("follow-rotated.1", b"");
+        let rotate = || {
+            // NOTE: Lines appended right before the rotation must not be lost.
+            file.append(&[line("b"), line("b")].concat());
+            std::fs::rename(&file.0, &rotated.0).unwrap();
+            std::fs::write(&file.0, line("c")).unwrap();
+        };
+        let analyzer = follow(&file, vec![(1, Box::new(rotate))]);
+        let expected = [("\"a\"", 1), ("\"b\"", 2), ("\"c\"", 1)];
+        let expected: Vec<_> = expected.iter().map(|&(k, n)| (k.to_string(), n)).collect();
+        assert_eq!(objects(&analyzer), expected);
+    }
+
+    #[test]
+    fn compressed_file() {
+        let file = TempFile::new("follow-compressed", &[0x1f, 0x8b, 0x08, 0x00]);
+        let mut analyzer = Analyzer::builder().build().unwrap();
+        let stop = AtomicBool::new(true);
+        let interval = Duration::from_secs(1);
+        let res = follow_file(&mut analyzer, file.0.as_os_str(), interval, &stop, |_| {
+            Ok(())
+        });
+        assert_eq!(
+            res.unwrap_err().to_string(),
+            "Compressed files cannot be followed"
+        );
+    }
+
+    #[test]
+    fn stop_processes_the_rest_of_the_file() {
+        let lines = 3 * CHUNK_SIZE / line("a").len();
+        let mut data = line("a").repeat(lines);
+        data.extend_from_slice(b"{\"type\":\"b\"}");
+        let file = TempFile::new("follow-stopped", &data);
+        let mut analyzer = Analyzer::builder().build().unwrap();
+        let stop = AtomicBool::new(true);
+        let interval = Duration::from_secs(1);
+        follow_file(&mut analyzer, file.0.as_os_str(), interval, &stop, |_| {
+            Ok(())
+        })
+        .unwrap();
+        let expected = vec![
+            ("\"a\"".to_string(), lines as u64),
+            ("\"b\"".to_string(), 1),
+        ];
+        assert_eq!(objects(&analyzer), expected);
+    }
+}
@@ -0,0 +1,232 @@
+use anyhow::{Context, Result};
+use memmap2::Mmap;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::ffi::OsStr;
+use std::fs::{File, Metadata};
+use std::io;
+use std::io::{BufRead, BufReader, Cursor, Read};
+use std::sync::atomic::{AtomicU64, Ordering::Relaxed};
+use std::sync::Arc;
+
+/// An opened input file.
+pub enum Input<'a> {
+    /// An uncompressed regular file that has been mapped into memory.
+    Mapped(Mmap),
+    /// Any other file.
+    Stream(Stream<'a>),
+}
+
+/// An input that is read sequentially.
+pub struct Stream<'a> {
+    /// The decompressed contents of the input.
+    pub reader: Box<dyn BufRead + Send + 'a>,
+    /// The number of compressed bytes consumed so far if the file is compressed.
+    pub compressed_bytes: Option<Arc<AtomicU64>>,
+}
+
+/// The compression formats that are detected automatically.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Compression {
+    Gzip,
+    Zstd,
+    Xz,
+    Bzip2,
+}
+
+const MAGIC: [(Compression, &[u8]); 4] = [
+    (Compression::Gzip, &[0x1f, 0x8b]),
+    (Compression::Zstd, &[0x28, 0xb5, 0x2f, 0xfd]),
+    (Compression::Xz, &[0xfd, b'7', b'z', b'X', b'Z', 0x00]),
+    (Compression::Bzip2, b"BZh"),
+];
+
+/// Opens a file. `-` refers to stdin.
+///
+/// Compressed files are detected by their magic bytes and decompressed transparently. If `mmap`
+/// is true, uncompressed regular files are mapped into memory.
+pub fn open(file: &OsStr, mmap: bool) -> Result<Input<'static>> {
+    let reader: Box<dyn Read + Send> = if file == "-" {
+        Box::new(io::stdin())
+    } else {
+        let file = File::open(file).context("Could not open the file")?;
+        if mmap {
+            if let Some(map) = map(&file) {
+                if !is_compressed(&map) {
+                    return Ok(Input::Mapped(map));
+                }
+            }
+        }
+        Box::new(file)
+    };
+    Ok(Input::Stream(open_reader(reader)?))
+}
+
+/// Prepares an input that is read sequentially. Compressed data is detected by its magic bytes
+/// and decompressed transparently.
+pub fn open_reader<'a>(reader: impl Read + Send + 'a) -> Result<Stream<'a>> {
+    let mut reader = BufReader::new(reader);
+    let header = read_header(&mut reader).context("Could not read from the file")?;
+    let compression = MAGIC
+        .iter()
+        .find(|(_, magic)| header.starts_with(magic))
+        .map(|(c, _)| *c);
+    let reader = Cursor::new(header).chain(reader);
+    let compression = match compression {
+        Some(c) => c,
+        None => {
+            return Ok(Stream {
+                reader: Box::new(BufReader::new(reader)),
+                compressed_bytes: None,
+            })
+        }
+    };
+    let compressed_bytes = Arc::new(AtomicU64::new(0));
+    let reader = CountingReader {
+        reader,
+        count: compressed_bytes.clone(),
+    };
+    let reader: Box<dyn Read + Send + 'a> = match compression {
+        Compression::Gzip => Box::new(flate2::read::MultiGzDecoder::new(reader)),
+        Compression::Zstd => Box::new(
+            zstd::stream::read::Decoder::new(reader)
+                .context("Could not initialize the zstd decoder")?,
+        ),
+        Compression::Xz => Box::new(xz2::read::XzDecoder::new_multi_decoder(reader)),
+        Compression::Bzip2 => Box::new(bzip2::read::MultiBzDecoder::new(reader)),
+    };
+    Ok(Stream {
+        reader: Box::new(BufReader::new(reader)),
+        compressed_bytes: Some(compressed_bytes),
+    })
+}
+
+/// Maps a file into memory if it is a non-empty regular file.
+///
+/// Returns `None` if the file cannot be mapped. The caller should then fall back to reading the
+/// file.
+fn map(file: &File) -> Option<Mmap> {
+    let metadata = file.metadata().ok()?;
+    if !metadata.is_file() || metadata.len() == 0 {
+        return None;
+    }
+    // SAFETY: The mapping is only valid as long as no other process truncates the file. Log files
+    // are usually only appended to, which is harmless since the length of the mapping is fixed.
+    // The --no-mmap flag can be used if this assumption does not hold.
+    unsafe { Mmap::map(file).ok() }
+}
+
+/// Returns whether data starting with `header` is compressed in any supported format.
+pub fn is_compressed(header: &[u8]) -> bool {
+    MAGIC.iter().any(|(_, magic)| header.starts_with(magic))
+}
+
+/// Reads enough bytes to identify all supported compression formats.
+///
+/// Fewer bytes are returned only if the file is shorter than that.
+pub fn read_header(reader: &mut impl Read) -> io::Result<Vec<u8>> {
+    let len = MAGIC.iter().map(|(_, m)| m.len()).max().unwrap_or_default();
+    let mut header = Vec::with_capacity(len);
+    reader.take(len as u64).read_to_end(&mut header)?;
+    Ok(header)
+}
+
+/// A reader that counts the number of bytes read from the underlying reader.
+struct CountingReader<R> {
+    reader: R,
+    count: Arc<AtomicU64>,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.reader.read(buf)?;
+        self.count.fetch_add(n as u64, Relaxed);
+        Ok(n)
+    }
+}
+
+/// The minimum size of a [`Chunk`] unless the end of the file is reached.
+pub const CHUNK_SIZE: usize = 1 << 20;
+
+/// A sequence of complete lines.
+pub struct Chunk<'a> {
+    /// The lines including their terminators. Only the last line of a file can be missing its
+    /// terminator. The data is borrowed if the file is mapped into memory.
+    pub data: Cow<'a, [u8]>,
+    /// The number of the first line in the chunk starting at 1.
+    pub first_line: u64,
+}
+
+/// Reads the next chunk of at least `CHUNK_SIZE` bytes (unless the end of the file is reached)
+/// ending at a line boundary. The chunk is read into `data`, which is cleared first, to allow
+/// buffers to be reused.
+///
+/// The chunk boundaries depend only on the contents of the file. Returns `None` at the end of the
+/// file.
+pub fn read_chunk(
+    reader: &mut impl BufRead,
+    mut data: Vec<u8>,
+    first_line: u64,
+) -> io::Result<Option<Chunk<'static>>> {
+    data.clear();
+    reader.take(CHUNK_SIZE as u64).read_to_end(&mut data)?;
+    if data.is_empty() {
+        return Ok(None);
+    }
+    if data.last() != Some(&b'\n') {
+        reader.read_until(b'\n', &mut data)?;
+    }
+    Ok(Some(Chunk {
+        data: Cow::Owned(data),
+        first_line,
+    }))
+}
+
+/// Returns the next chunk of a file that has been mapped into memory. `pos` is the offset of the
+/// chunk in the file and is advanced to the end of the chunk.
+///
+/// The chunk boundaries are the same as those produced by [`read_chunk`].
+pub fn next_mapped_chunk<'a>(
+    data: &'a [u8],
+    pos: &mut usize,
+    first_line: u64,
+) -> Option<Chunk<'a>> {
+    let rest = &data[*pos..];
+    if rest.is_empty() {
+        return None;
+    }
+    let mut len = rest.len().min(CHUNK_SIZE);
+    if rest[len - 1] != b'\n' {
+        len = match memchr::memchr(b'\n', &rest[len..]) {
+            Some(n) => len + n + 1,
+            None => rest.len(),
+        };
+    }
+    *pos += len;
+    Some(Chunk {
+        data: Cow::Borrowed(&rest[..len]),
+        first_line,
+    })
+}
+
+/// Identifies a file independently of its path.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileId {
+    dev: u64,
+    ino: u64,
+}
+
+#[cfg(unix)]
+pub fn file_id(metadata: &Metadata) -> Option<FileId> {
+    use std::os::unix::fs::MetadataExt;
+    Some(FileId {
+        dev: metadata.dev(),
+        ino: metadata.ino(),
+    })
+}
+
+// NOTE: On other platforms, only truncation is detected.
+#[cfg(not(unix))]
+pub fn file_id(_metadata: &Metadata) -> Option<FileId> {
+    None
+}
@@ -0,0 +1,281 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Number, Value};
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+/// A json value that can be used as the key of a `HashMap`.
+///
+/// `serde_json::Value` implements neither `Hash` nor `Eq` nor `Ord`. This wrapper implements
+/// these traits in a way that is consistent with the `PartialEq` implementation of
+/// `serde_json::Value`. That is, two keys are equal if and only if the wrapped values are equal.
+///
+/// The `Display` implementation prints the value in its compact json form.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Key(pub Value);
+
+// NOTE: json cannot represent NaN. Therefore, the float comparison in `PartialEq` is reflexive.
+impl Eq for Key {}
+
+impl Hash for Key {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        hash_value(&self.0, state);
+    }
+}
+
+fn hash_value<H: Hasher>(value: &Value, state: &mut H) {
+    kind_rank(value).hash(state);
+    match value {
+        Value::Null => {}
+        Value::Bool(b) => b.hash(state),
+        Value::Number(n) => hash_number(n, state),
+        Value::String(s) => s.hash(state),
+        Value::Array(a) => {
+            a.len().hash(state);
+            for v in a {
+                hash_value(v, state);
+            }
+        }
+        Value::Object(o) => {
+            o.len().hash(state);
+            // NOTE: Without the `preserve_order` feature, `serde_json::Map` is a `BTreeMap` and
+            // therefore iterates in a canonical order.
+            for (k, v) in o {
+                k.hash(state);
+                hash_value(v, state);
+            }
+        }
+    }
+}
+
+fn hash_number<H: Hasher>(n: &Number, state: &mut H) {
+    // NOTE: serde_json considers integers and floats to be different even if they represent the
+    // same number. The only float values that compare equal but have different bit patterns are
+    // 0.0 and -0.0. Adding 0.0 turns the latter into the former.
+    if let Some(u) = n.as_u64() {
+        (0u8, u).hash(state);
+    } else if let Some(i) = n.as_i64() {
+        (1u8, i).hash(state);
+    } else if let Some(f) = n.as_f64() {
+        (2u8, (f + 0.0).to_bits()).hash(state);
+    }
+}
+
+impl PartialOrd for Key {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Values are ordered first by their kind (null < bool < number < string < array < object) and
+/// then by their contents. This ensures that string keys are ordered exactly as before.
+impl Ord for Key {
+    fn cmp(&self, other: &Self) -> Ordering {
+        cmp_value(&self.0, &other.0)
+    }
+}
+
+fn kind_rank(value: &Value) -> u8 {
+    match value {
+        Value::Null => 0,
+        Value::Bool(_) => 1,
+        Value::Number(_) => 2,
+        Value::String(_) => 3,
+        Value::Array(_) => 4,
+        Value::Object(_) => 5,
+    }
+}
+
+fn cmp_value(l: &Value, r: &Value) -> Ordering {
+    match (l, r) {
+        (Value::Bool(l), Value::Bool(r)) => l.cmp(r),
+        (Value::Number(l), Value::Number(r)) => cmp_number(l, r),
+        (Value::String(l), Value::String(r)) => l.cmp(r),
+        (Value::Array(l), Value::Array(r)) => l
+            .iter()
+            .zip(r.iter())
+            .map(|(l, r)| cmp_value(l, r))
+            .find(|o| o.is_ne())
+            .unwrap_or_else(|| l.len().cmp(&r.len())),
+        (Value::Object(l), Value::Object(r)) => l
+            .iter()
+            .zip(r.iter())
+            .map(|((lk, lv), (rk, rv))| lk.cmp(rk).then_with(|| cmp_value(lv, rv)))
+            .find(|o| o.is_ne())
+            .unwrap_or_else(|| l.len().cmp(&r.len())),
+        _ => kind_rank(l).cmp(&kind_rank(r)),
+    }
+}
+
+/// A json number split into the representations used by serde_json.
+enum Num {
+    Int(i128),
+    Float(f64),
+}
+
+fn to_num(n: &Number) -> Num {
+    if let Some(u) = n.as_u64() {
+        Num::Int(u as i128)
+    } else if let Some(i) = n.as_i64() {
+        Num::Int(i as i128)
+    } else {
+        Num::Float(n.as_f64().unwrap_or_default())
+    }
+}
+
+fn cmp_number(l: &Number, r: &Number) -> Ordering {
+    match (to_num(l), to_num(r)) {
+        (Num::Int(l), Num::Int(r)) => l.cmp(&r),
+        (Num::Float(l), Num::Float(r)) => l.partial_cmp(&r).unwrap_or(Ordering::Equal),
+        (Num::Int(l), Num::Float(r)) => cmp_int_float(l, r),
+        (Num::Float(l), Num::Int(r)) => cmp_int_float(r, l).reverse(),
+    }
+}
+
+/// Compares an integer with a float exactly. Integers are ordered before floats of the same
+/// value since serde_json does not consider them equal.
+fn cmp_int_float(i: i128, f: f64) -> Ordering {
+    // NOTE: If the rounded integer differs from the float, then it is on the same side of the
+    // float as the exact integer because the float would otherwise be a better approximation.
+    // Otherwise the float is an integer in the range of i65 and can be compared exactly.
+    match (i as f64).partial_cmp(&f) {
+        Some(Ordering::Equal) | None => i.cmp(&(f as i128)).then(Ordering::Less),
+        Some(o) => o,
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// The values of all key fields of an entry in the order in which the fields were specified.
+///
+/// `None` marks a key field that the entry does not contain. Absent values are ordered before all
+/// other values.
+pub type GroupKey = Vec<Option<Key>>;
+
+/// Formats one component of a [`GroupKey`] for display.
+pub fn display_component(component: &Option<Key>) -> String {
+    match component {
+        Some(key) => key.to_string(),
+        None => "<absent>".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::collections::hash_map::DefaultHasher;
+
+    fn key(s: &str) -> Key {
+        Key(serde_json::from_str(s).unwrap())
+    }
+
+    fn hash(key: &Key) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Keys of all kinds in ascending order.
+    const SORTED: [&str; 23] = [
+        "null",
+        "false",
+        "true",
+        "-1e300",
+        "-9223372036854775808",
+        "-2.5",
+        "-2",
+        "0",
+        "0.0",
+        "0.5",
+        "1",
+        "1.0",
+        "9007199254740992.0",
+        "9007199254740993",
+        "18446744073709551615",
+        "1e300",
+        r#""""#,
+        r#""a""#,
+        r#""b""#,
+        "[]",
+        "[1]",
+        "[1,2]",
+        r#"{"a":1}"#,
+    ];
+
+    #[test]
+    fn order() {
+        let keys: Vec<Key> = SORTED.iter().map(|s| key(s)).collect();
+        for (i, l) in keys.iter().enumerate() {
+            for (j, r) in keys.iter().enumerate() {
+                assert_eq!(l.cmp(r), i.cmp(&j), "{} <=> {}", l, r);
+            }
+        }
+    }
+
+    #[test]
+    fn consistent_with_eq() {
+        let keys: Vec<Key> = SORTED.iter().map(|s| key(s)).collect();
+        for l in &keys {
+            for r in &keys {
+                assert_eq!(l == r, l.cmp(r).is_eq(), "{} == {}", l, r);
+                if l == r {
+                    assert_eq!(hash(l), hash(r), "{} == {}", l, r);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn negative_zero() {
+        let (zero, negative) = (key("0.0"), key("-0.0"));
+        assert_eq!(zero, negative);
+        assert_eq!(zero.cmp(&negative), Ordering::Equal);
+        assert_eq!(hash(&zero), hash(&negative));
+        // NOTE: serde_json does not consider integers and floats equal.
+        assert_ne!(key("0"), zero);
+    }
+
+    #[test]
+    fn objects_are_canonical() {
+        let l = key(r#"{"a":1,"b":[true,null]}"#);
+        let r = key(r#"{"b":[true,null],"a":1}"#);
+        assert_eq!(l, r);
+        assert_eq!(l.cmp(&r), Ordering::Equal);
+        assert_eq!(hash(&l), hash(&r));
+        assert_eq!(l.to_string(), r#"{"a":1,"b":[true,null]}"#);
+    }
+
+    #[test]
+    fn integers_and_floats_near_the_precision_limit() {
+        // NOTE: 2^53 + 1 is rounded to 2^53 when converted to a float.
+        let int = Key(json!(9007199254740993u64));
+        let float = Key(json!(9007199254740992.0));
+        assert_eq!(int.cmp(&float), Ordering::Greater);
+        assert_eq!(Key(json!(9007199254740992u64)).cmp(&float), Ordering::Less);
+        assert_eq!(Key(json!(-1)).cmp(&Key(json!(-1.0))), Ordering::Less);
+        assert_eq!(
+            Key(json!(u64::MAX)).cmp(&Key(json!(1.8446744073709552e19))),
+            Ordering::Less
+        );
+    }
+
+    #[test]
+    fn absent_components() {
+        let mut keys: Vec<GroupKey> = vec![
+            vec![Some(key(r#""a""#)), None],
+            vec![None, Some(key("1"))],
+            vec![Some(key(r#""a""#)), Some(key("null"))],
+        ];
+        keys.sort();
+        assert_eq!(keys[0][0], None);
+        assert_eq!(keys[1][1], None);
+        assert_eq!(display_component(&keys[0][0]), "<absent>");
+        assert_eq!(display_component(&keys[2][1]), "null");
+    }
+}
@@ -0,0 +1,60 @@
+//! Analyzes the occurrences of entry types in logs containing one json object per line.
+//!
+//! The entries are grouped by the values of one or more key fields (by default `type`). For each
+//! group, the number of entries and the bytes used by them are counted. Optionally, statistics of
+//! a numeric field, the number of distinct values of a field, and the fields of the entries are
+//! collected.
+//!
+//! ```
+//! use log_analyzer::Analyzer;
+//!
+//! let mut analyzer = Analyzer::builder().build()?;
+//! analyzer.feed_line(br#"{"type": "login", "user": "alice"}"#)?;
+//! analyzer.feed_line(br#"{"type": "logout"}"#)?;
+//! analyzer.feed_reader(&b"{\"type\": \"login\"}\n"[..])?;
+//! let report = analyzer.report();
+//! let (key, login) = &report.types()[0];
+//! assert_eq!(key[0].as_ref().unwrap().0, "login");
+//! assert_eq!(login.objects(), 2);
+//! assert_eq!(report.total().objects(), 3);
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+mod analyzer;
+mod checkpoint;
+mod diff;
+mod distinct;
+mod duration;
+mod errors;
+mod filter;
+mod follow;
+mod input;
+mod key;
+mod path;
+mod pipeline;
+mod process;
+mod report;
+mod scan;
+mod schema;
+mod seek;
+mod snapshot;
+mod stats;
+mod table;
+#[cfg(test)]
+mod test_util;
+mod time;
+mod values;
+
+pub use crate::analyzer::{Analyzer, AnalyzerBuilder, INPUT_NAME};
+pub use crate::diff::{Diff, Thresholds};
+pub use crate::duration::parse_duration;
+pub use crate::errors::{ErrorKind, ErrorStats, Location, OnError, Utf8Mode};
+pub use crate::filter::{Filter, ParseError as FilterError};
+pub use crate::key::{GroupKey, Key};
+pub use crate::path::FieldPath;
+pub use crate::report::{Format, Report, SortKey, SCHEMA_VERSION};
+pub use crate::schema::{Field, Kind, Schema};
+pub use crate::snapshot::Snapshot;
+pub use crate::stats::{size_bucket_range, ByteMode, SizeStats, TypeData};
+pub use crate::time::{parse_time, Buckets, TimestampFormat};
+pub use crate::values::{ValueStats, QUANTILES};
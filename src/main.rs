@@ -1,102 +1,904 @@
-use anyhow::{anyhow, Context, Result};
-use clap::Parser;
-use serde::Deserialize;
-use std::collections::HashMap;
-use std::ffi::OsString;
+use anyhow::{Context, Result};
+use clap::{ArgEnum, Parser, Subcommand};
+use log_analyzer::{
+    parse_duration, parse_time, Analyzer, Buckets, ByteMode, Diff, ErrorKind, ErrorStats,
+    FieldPath, Filter, Format, OnError, Report, Snapshot, SortKey, Thresholds, TimestampFormat,
+    Utf8Mode,
+};
+use std::ffi::{OsStr, OsString};
+use std::fs;
 use std::fs::File;
 use std::io;
-use std::io::{BufRead, BufReader};
+use std::io::{BufWriter, IsTerminal, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering::Relaxed};
+use std::sync::Arc;
+use std::time::Duration;
 
-/// Analyzes the occurrences of entry types in a log file
+/// Analyzes the occurrences of entry types in log files
 ///
-/// Each line in the input file should contain a complete json object containing a `type`
-/// field. The entries in the file will be grouped by this type and for each unique type
-/// the following statistics will be printed:
+/// Each line in the input files should contain a complete json object containing a `type`
+/// field. The type can be any json value and different fields can be selected with `--key`.
+/// The entries in the file will be grouped by this type and for each unique type (printed in its
+/// json form) the following statistics will be printed:
 ///
-/// The number of entries with this type. The space used (in bytes, excluding the line terminator)
-/// by all entries with this type.
+/// The number of entries with this type. The space used (in bytes, counted as selected with
+/// `--bytes`) by all entries with this type.
 // NOTE: The previous paragraph should be a markdown list but I couldn't figure out how to make
 // clap not merge adjacent lines.
 #[derive(Parser, Debug)]
-#[clap(max_term_width = 90)]
+#[clap(max_term_width = 90, args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[clap(subcommand)]
+    command: Option<Command>,
+    #[clap(flatten)]
+    args: Args,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print the fields of each type
+    ///
+    /// For each type, every field path that occurs in the entries is reported together with the
+    /// kinds of json values observed at the path, the percentage of entries containing the path,
+    /// and up to three example values. Paths are JSON Pointers in which the elements of arrays
+    /// are represented by the segment `*`. All options of the analysis apply.
+    #[clap(max_term_width = 90)]
+    Schema {
+        #[clap(flatten)]
+        schema: SchemaArgs,
+        #[clap(flatten)]
+        args: Args,
+    },
+    /// Compare the types and their fields in two inputs
+    ///
+    /// Both inputs are analyzed with the same options. The report lists the types occurring in
+    /// either input with the changes of their numbers of objects and bytes, followed by the
+    /// fields that were added, removed, or whose kinds changed (see the `schema` subcommand).
+    /// If one of the `--fail-on-*` or `--max-*-change` conditions is met, the program exits
+    /// with status 3 after printing the report.
+    #[clap(max_term_width = 90)]
+    Diff {
+        #[clap(flatten)]
+        thresholds: ThresholdArgs,
+        #[clap(flatten)]
+        args: Args,
+    },
+    /// Combine snapshots written with `--snapshot` into a single report
+    ///
+    /// The snapshots must have been created with the same key fields, `--value`, `--distinct`,
+    /// `--exact-percentiles`, `--exact-distinct`, and `--count-compressed` options and by the
+    /// same subcommand. The report does not depend on the order of the snapshots except for the
+    /// locations of skipped lines, which are taken from the first snapshots.
+    #[clap(max_term_width = 90)]
+    Merge {
+        /// The snapshots to combine
+        ///
+        /// `-` refers to stdin.
+        #[clap(required = true, value_name = "SNAPSHOTS")]
+        snapshots: Vec<OsString>,
+        #[clap(flatten)]
+        output: OutputArgs,
+    },
+}
+
+/// The exit status of the `diff` subcommand if a threshold is exceeded.
+const DIFF_FAILURE: i32 = 3;
+
+#[derive(clap::Args, Debug, Default)]
+struct SchemaArgs {
+    /// Print the schema as a JSON Schema (draft 2020-12) instead of a field inventory
+    ///
+    /// The document accepts any entry that matches the schema of one of the types. `--format`
+    /// is ignored.
+    #[clap(long)]
+    json_schema: bool,
+}
+
+/// The options of the report.
+#[derive(clap::Args, Debug)]
+struct OutputArgs {
+    /// The maximum ratio of skipped lines before the program exits with an error
+    ///
+    /// The report is printed either way. By default, skipped lines never cause an error. For
+    /// example, `0.01` fails if more than 1% of the lines were skipped.
+    #[clap(long, default_value = "1", value_name = "RATIO")]
+    max_error_ratio: f64,
+    /// The format of the report
+    ///
+    /// All formats except `human` contain a `schema_version` field that changes whenever the
+    /// meaning of an existing field changes.
+    #[clap(short, long, arg_enum, default_value = "human")]
+    format: FormatArg,
+    /// The order of the types in the report
+    #[clap(long, arg_enum, default_value = "name")]
+    sort: SortKeyArg,
+    /// Reverse the order of the types in the report
+    #[clap(long)]
+    reverse: bool,
+    /// Only report the first N types and combine the remaining types into a single `<other>` row
+    #[clap(long, value_name = "N")]
+    top: Option<usize>,
+    /// Also write the aggregated state to this file
+    ///
+    /// Snapshots of different inputs, e.g. of the logs of different hosts, can be combined into a
+    /// single report with the `merge` subcommand.
+    #[clap(long, value_name = "FILE")]
+    snapshot: Option<OsString>,
+}
+
+#[derive(clap::Args, Debug)]
 struct Args {
-    /// The file to analyze
-    file: OsString,
-}
-
-#[derive(Default)]
-struct TypeData {
-    /// The number of entries with this type.
-    num: u64,
-    /// The number of bytes used by all entries with this type.
-    bytes: u64,
-}
-
-// NOTE: serde_json ignores unknown fields by default.
-#[derive(Deserialize)]
-struct JsonObject {
-    // NOTE: The exercise description does not specify the type of the "type" field. So it would not
-    // be incorrect for the type to be a number or an array. This program would error out on such type
-    // fields. Therefore, the type of this field should really by `serde_json::Value` which can hold
-    // any kind of json value. Unfortunately, `serde_json::Value` does not implement `Hash` and can
-    // therefore not be used easily as the key in a HashMap. Therefore we would have to implement a
-    // small wrapper type around `serde_json::Value` that implements `Hash`, `PartialEq`, and
-    // `Deserialize`.
-    //
-    // However, since the example always used string types, I've decided to go with this field type for
-    // the exercise. In a real project, I would ask for the requirements to be clarified first.
-    #[serde(rename = "type")]
-    ty: String,
+    /// The files to analyze
+    ///
+    /// `-` refers to stdin. If no files are given, stdin is analyzed. The results of all files
+    /// are merged into a single report.
+    files: Vec<OsString>,
+    /// Add the file name as the first key field to break down the report by input file
+    ///
+    /// The file name is the key field `file`, so no other key field may be called `file`.
+    #[clap(long)]
+    per_file: bool,
+    /// A field to group the entries by
+    ///
+    /// This is either a JSON Pointer such as `/meta/category` or a dotted path such as
+    /// `event.kind`. If this option is used multiple times, the entries are grouped by the
+    /// combination of all fields. Fields missing from an entry are reported as `<absent>`.
+    #[clap(short, long = "key", default_value = "type")]
+    keys: Vec<FieldPath>,
+    /// Treat lines that contain none of the key fields as errors
+    ///
+    /// By default, such lines are grouped under a key where all fields are `<absent>`.
+    #[clap(long)]
+    require_key: bool,
+    /// What to do with lines that cannot be processed
+    ///
+    /// Lines that are not valid UTF-8, not valid json, that do not contain the key fields (with
+    /// `--require-key`), or that do not contain a valid timestamp can either abort the analysis
+    /// or be skipped. Skipped lines are summarized at the end. See `--max-error-ratio` for the
+    /// exit status.
+    #[clap(long, arg_enum, default_value = "fail")]
+    on_error: OnErrorArg,
+    /// How lines that are not valid UTF-8 are decoded
+    ///
+    /// By default, such lines cannot be processed. Otherwise they are decoded and processed like
+    /// other lines. In either case, the number of lines that are not valid UTF-8 is reported at
+    /// the end.
+    #[clap(long, arg_enum, default_value = "strict", value_name = "MODE")]
+    utf8: Utf8Arg,
+    /// The maximum number of line numbers to report per kind of error
+    #[clap(long, default_value = "10", value_name = "N")]
+    error_samples: usize,
+    #[clap(flatten)]
+    output: OutputArgs,
+    /// Count the compressed size of the entries in compressed files
+    ///
+    /// Compressed files (gzip, zstd, xz, and bzip2) are decompressed automatically and by default
+    /// the sizes refer to the decompressed contents. With this flag, the size of the compressed
+    /// file is instead distributed over the entries in proportion to their decompressed size
+    /// (including line terminators). This implies `--bytes raw`.
+    #[clap(long)]
+    count_compressed: bool,
+    /// Which bytes of a line are counted as the size of the entry
+    ///
+    /// By default, the line terminator is not counted. `newline` counts a `\n` terminator as one
+    /// byte even if it is preceded by `\r`, and `raw` counts the line exactly as stored. The
+    /// report ends with a summary that reconciles the total with the number of bytes read.
+    #[clap(
+        long,
+        arg_enum,
+        default_value = "content",
+        value_name = "MODE",
+        conflicts_with = "count-compressed"
+    )]
+    bytes: ByteModeArg,
+    /// The number of threads used to parse the input
+    ///
+    /// Defaults to the number of CPUs. The report does not depend on the number of threads. The
+    /// number must not be zero.
+    #[clap(short = 'j', long, value_name = "N", parse(try_from_str = parse_threads))]
+    threads: Option<usize>,
+    /// Read regular files instead of mapping them into memory
+    ///
+    /// Memory-mapped files are faster to process but the program might crash if another process
+    /// truncates a file while it is being analyzed.
+    #[clap(long)]
+    no_mmap: bool,
+    /// Keep reading the file after reaching its end and print the report periodically
+    ///
+    /// Requires exactly one uncompressed file. The file is processed again from the start if it is
+    /// replaced (e.g. by log rotation) or truncated. Press Ctrl-C to print the final report and
+    /// exit.
+    #[clap(long)]
+    follow: bool,
+    /// Only include entries that match this filter
+    ///
+    /// Example: `level == "error" and (status >= 500 or not exists status)`. Supported are
+    /// comparisons (`==`, `!=`, `<`, `<=`, `>`, `>=`) with json values, regular expression matches
+    /// (`=~`, `!~`) of string fields, `exists`, `and`, `or`, `not`, and parentheses. Fields are
+    /// paths as accepted by `--key`. If this option is used multiple times, entries must match all
+    /// filters. Entries that do not match are counted separately from skipped lines.
+    #[clap(long = "filter", value_name = "EXPR")]
+    filters: Vec<Filter>,
+    /// A numeric field to compute summary statistics of
+    ///
+    /// For each type, the count, sum, minimum, maximum, mean, standard deviation, and the 50th,
+    /// 90th, and 99th percentiles of the values of this field are reported. Entries in which the
+    /// field is missing or not a number are ignored. The percentiles are approximated with a
+    /// relative error of at most 1% unless `--exact-percentiles` is used.
+    #[clap(long, value_name = "FIELD")]
+    value: Option<FieldPath>,
+    /// Compute the percentiles of `--value` exactly
+    ///
+    /// This requires memory proportional to the number of entries.
+    #[clap(long, requires = "value")]
+    exact_percentiles: bool,
+    /// A field whose distinct values are counted
+    ///
+    /// For each type, the number of distinct values of this field is reported. Entries in which
+    /// the field is missing are ignored. Up to 512 distinct values are counted exactly. Beyond
+    /// that, the count is estimated with a HyperLogLog sketch with a standard error of about 1.6%
+    /// unless `--exact-distinct` is used.
+    #[clap(long, value_name = "FIELD")]
+    distinct: Option<FieldPath>,
+    /// Count the distinct values of `--distinct` exactly
+    ///
+    /// This requires memory proportional to the number of distinct values.
+    #[clap(long, requires = "distinct")]
+    exact_distinct: bool,
+    /// A field containing the time of the entry
+    ///
+    /// This is a path in the same form as `--key`. Required by `--bucket`.
+    #[clap(long, value_name = "FIELD")]
+    timestamp: Option<FieldPath>,
+    /// The format of the timestamp field
+    ///
+    /// One of `rfc3339`, `epoch-seconds`, `epoch-millis`, or a strftime format such as
+    /// `%d/%b/%Y:%H:%M:%S %z`. Timestamps without a time zone are interpreted as UTC.
+    #[clap(long, default_value = "rfc3339", value_name = "FORMAT")]
+    timestamp_format: TimestampFormat,
+    /// Break down the report into time buckets of this width
+    ///
+    /// The buckets are aligned to the Unix epoch. The `human`, `csv`, and `tsv` formats contain
+    /// one row per type with the numbers of objects and bytes in each bucket. The json formats
+    /// contain one entry per type and bucket in which the start of the bucket is the last key
+    /// field `time`, so no other key field may be called `time`. Examples: `1m`, `1h`, `1d`.
+    #[clap(long, value_name = "DURATION", requires = "timestamp")]
+    bucket: Option<Buckets>,
+    /// Exclude entries whose timestamps are before this time
+    ///
+    /// This is either a timestamp such as `2022-05-01T12:00:00Z` or `2022-05-01` (interpreted as
+    /// UTC if no time zone is given) or a duration before the current time such as `-2h`.
+    /// Excluded lines are counted separately from skipped lines.
+    #[clap(
+        long,
+        value_name = "TIME",
+        requires = "timestamp",
+        allow_hyphen_values = true,
+        parse(try_from_str = parse_time_bound)
+    )]
+    since: Option<TimeBound>,
+    /// Exclude entries whose timestamps are at or after this time
+    ///
+    /// The format is the same as for `--since`.
+    #[clap(
+        long,
+        value_name = "TIME",
+        requires = "timestamp",
+        allow_hyphen_values = true,
+        parse(try_from_str = parse_time_bound)
+    )]
+    until: Option<TimeBound>,
+    /// Assume that the entries are sorted by their timestamps
+    ///
+    /// With `--since` or `--until`, this allows the time range to be located by binary search
+    /// instead of scanning the whole file. This only applies to files that are mapped into
+    /// memory. Lines outside of the time range are then counted as excluded without being
+    /// validated.
+    #[clap(long, requires = "timestamp")]
+    sorted: bool,
+    /// How often the report is printed in follow mode
+    ///
+    /// Examples: `500ms`, `2s`, `1m`. The interval must not be zero.
+    #[clap(
+        long,
+        default_value = "2s",
+        value_name = "DURATION",
+        parse(try_from_str = parse_interval)
+    )]
+    interval: Duration,
+    /// Resume the analysis from this checkpoint and update it afterwards
+    ///
+    /// The checkpoint contains the aggregated state and the position up to which each file has
+    /// been processed. If it exists, only the lines appended to the files since it was written
+    /// are processed. If a file has been replaced or truncated, or if the options or files
+    /// differ, all files are processed from the start. Compressed files are skipped if they are
+    /// unchanged and processed from the start otherwise. The checkpoint is also a snapshot (see
+    /// `--snapshot`). A relative `--since` or `--until` cannot be used since the time range would
+    /// differ between runs.
+    #[clap(long, value_name = "FILE", conflicts_with = "follow")]
+    checkpoint: Option<OsString>,
+    /// The subcommand that is being run.
+    #[clap(skip)]
+    mode: Mode,
+}
+
+#[derive(Debug, Default)]
+enum Mode {
+    #[default]
+    Report,
+    Schema(SchemaArgs),
+    Diff(ThresholdArgs),
+}
+
+// NOTE: The library does not depend on clap. The following types mirror the library types that
+// are selected on the command line.
+
+/// See [`Format`].
+#[derive(ArgEnum, Copy, Clone, Debug)]
+enum FormatArg {
+    /// An aligned table
+    Human,
+    /// A single json document
+    Json,
+    /// Comma-separated values with a header row
+    Csv,
+    /// Tab-separated values with a header row
+    Tsv,
+    /// One json object per line
+    Ndjson,
+}
+
+impl From<FormatArg> for Format {
+    fn from(format: FormatArg) -> Self {
+        match format {
+            FormatArg::Human => Format::Human,
+            FormatArg::Json => Format::Json,
+            FormatArg::Csv => Format::Csv,
+            FormatArg::Tsv => Format::Tsv,
+            FormatArg::Ndjson => Format::Ndjson,
+        }
+    }
+}
+
+/// See [`SortKey`].
+#[derive(ArgEnum, Copy, Clone, Debug)]
+enum SortKeyArg {
+    /// By the values of the key fields
+    Name,
+    /// By the number of objects, largest first
+    Count,
+    /// By the total bytes, largest first
+    Bytes,
+    /// By the average size of the objects, largest first
+    AvgSize,
+}
+
+impl From<SortKeyArg> for SortKey {
+    fn from(key: SortKeyArg) -> Self {
+        match key {
+            SortKeyArg::Name => SortKey::Name,
+            SortKeyArg::Count => SortKey::Count,
+            SortKeyArg::Bytes => SortKey::Bytes,
+            SortKeyArg::AvgSize => SortKey::AvgSize,
+        }
+    }
+}
+
+/// See [`OnError`].
+#[derive(ArgEnum, Copy, Clone, Debug)]
+enum OnErrorArg {
+    /// Abort the analysis
+    Fail,
+    /// Skip the line and print a warning
+    Warn,
+    /// Skip the line silently
+    Skip,
+}
+
+impl From<OnErrorArg> for OnError {
+    fn from(on_error: OnErrorArg) -> Self {
+        match on_error {
+            OnErrorArg::Fail => OnError::Fail,
+            OnErrorArg::Warn => OnError::Warn,
+            OnErrorArg::Skip => OnError::Skip,
+        }
+    }
+}
+
+/// See [`Utf8Mode`].
+#[derive(ArgEnum, Copy, Clone, Debug)]
+enum Utf8Arg {
+    /// Treat the line as an error
+    Strict,
+    /// Replace invalid bytes by U+FFFD
+    Lossy,
+    /// Escape invalid bytes in strings as `\u00XX` so that different values stay distinct and
+    /// replace the others by U+FFFD
+    Escape,
+}
+
+impl From<Utf8Arg> for Utf8Mode {
+    fn from(mode: Utf8Arg) -> Self {
+        match mode {
+            Utf8Arg::Strict => Utf8Mode::Strict,
+            Utf8Arg::Lossy => Utf8Mode::Lossy,
+            Utf8Arg::Escape => Utf8Mode::Escape,
+        }
+    }
+}
+
+/// See [`ByteMode`].
+#[derive(ArgEnum, Copy, Clone, Debug)]
+enum ByteModeArg {
+    /// The content of the line without its terminator
+    Content,
+    /// The content plus one byte for the `\n` terminator, ignoring the `\r` of a `\r\n`
+    Newline,
+    /// The line as stored, including a `\n` or `\r\n` terminator
+    Raw,
+}
+
+impl From<ByteModeArg> for ByteMode {
+    fn from(mode: ByteModeArg) -> Self {
+        match mode {
+            ByteModeArg::Content => ByteMode::Content,
+            ByteModeArg::Newline => ByteMode::Newline,
+            ByteModeArg::Raw => ByteMode::Raw,
+        }
+    }
+}
+
+/// See [`Thresholds`].
+#[derive(clap::Args, Debug)]
+struct ThresholdArgs {
+    /// Fail if a type occurs only in the new input
+    #[clap(long)]
+    fail_on_added_types: bool,
+    /// Fail if a type occurs only in the old input
+    #[clap(long)]
+    fail_on_removed_types: bool,
+    /// Fail if a field of a type occurring in both inputs was added or removed or its kinds
+    /// changed
+    #[clap(long)]
+    fail_on_field_changes: bool,
+    /// Fail if the number of objects of a type occurring in both inputs changed by more than
+    /// this percentage
+    #[clap(long, value_name = "PERCENT")]
+    max_objects_change: Option<f64>,
+    /// Fail if the number of bytes of a type occurring in both inputs changed by more than this
+    /// percentage
+    #[clap(long, value_name = "PERCENT")]
+    max_bytes_change: Option<f64>,
+}
+
+impl From<&ThresholdArgs> for Thresholds {
+    fn from(args: &ThresholdArgs) -> Self {
+        let mut thresholds = Thresholds::default();
+        thresholds.fail_on_added_types = args.fail_on_added_types;
+        thresholds.fail_on_removed_types = args.fail_on_removed_types;
+        thresholds.fail_on_field_changes = args.fail_on_field_changes;
+        thresholds.max_objects_change = args.max_objects_change;
+        thresholds.max_bytes_change = args.max_bytes_change;
+        thresholds
+    }
 }
 
 fn main() {
-    let args = Args::parse();
+    let cli = Cli::parse();
+    let mut args = match cli.command {
+        None => cli.args,
+        Some(Command::Schema { schema, mut args }) => {
+            args.mode = Mode::Schema(schema);
+            args
+        }
+        Some(Command::Diff {
+            thresholds,
+            mut args,
+        }) => {
+            args.mode = Mode::Diff(thresholds);
+            args
+        }
+        Some(Command::Merge { snapshots, output }) => merge(&snapshots, &output),
+    };
+    if args.files.is_empty() {
+        args.files.push("-".into());
+    }
+
+    // NOTE: The subcommand validates its options itself so that its errors name it.
+    if let Mode::Diff(thresholds) = &args.mode {
+        diff(&args, thresholds);
+    }
 
-    let result = match process_file(&args.file) {
-        Ok(r) => r,
+    if args.follow && (args.files.len() != 1 || args.files[0] == "-") {
+        eprintln!("--follow requires exactly one file other than stdin");
+        std::process::exit(1);
+    }
+
+    if args.checkpoint.is_some() && args.files.iter().any(|f| f == "-") {
+        eprintln!("--checkpoint cannot be used with stdin");
+        std::process::exit(1);
+    }
+
+    let relative = [args.since, args.until]
+        .iter()
+        .flatten()
+        .any(|b| b.relative);
+    if args.checkpoint.is_some() && relative {
+        eprintln!("--checkpoint cannot be used with a relative --since or --until");
+        std::process::exit(1);
+    }
+
+    let analyzer = analyze(&args, &args.files);
+    let report = report(&args.output, analyzer.report());
+
+    if let Err(e) = write_report(&args.mode, args.output.format.into(), &report) {
+        eprintln!("Could not write the report: {}", e);
+        std::process::exit(1);
+    }
+
+    if args.output.snapshot.is_some() || args.checkpoint.is_some() {
+        let snapshot = analyzer.snapshot();
+        for path in args.output.snapshot.iter().chain(&args.checkpoint) {
+            write_snapshot(path, &snapshot);
+        }
+    }
+
+    if !print_summary(&args.output, &report, None, args.files.len() > 1) {
+        std::process::exit(1);
+    }
+}
+
+/// Runs the `diff` subcommand.
+fn diff(args: &Args, thresholds: &ThresholdArgs) -> ! {
+    if args.follow || args.per_file || args.output.snapshot.is_some() || args.checkpoint.is_some() {
+        eprintln!("diff cannot be used with --follow, --per-file, --snapshot, or --checkpoint");
+        std::process::exit(1);
+    }
+    if args.files.len() != 2 {
+        eprintln!("diff requires exactly two files");
+        std::process::exit(1);
+    }
+    let old = analyze(args, &args.files[..1]).report();
+    let new = analyze(args, &args.files[1..]).report();
+    let diff = Diff::new(&old, &new);
+    let res = (|| {
+        let mut stdout = BufWriter::new(io::stdout().lock());
+        diff.write(args.output.format.into(), &mut stdout)?;
+        stdout.flush()
+    })();
+    if let Err(e) = res {
+        eprintln!("Could not write the report: {}", e);
+        std::process::exit(1);
+    }
+    let mut ok = true;
+    for (file, report) in args.files.iter().zip([&old, &new]) {
+        let name = file.to_string_lossy();
+        ok &= print_summary(&args.output, report, Some(&name), false);
+    }
+    if !ok {
+        std::process::exit(1);
+    }
+    let violations = diff.violations(&thresholds.into());
+    for violation in &violations {
+        eprintln!("{}", violation);
+    }
+    std::process::exit(match violations.is_empty() {
+        true => 0,
+        false => DIFF_FAILURE,
+    });
+}
+
+/// Runs the `merge` subcommand.
+fn merge(snapshots: &[OsString], output: &OutputArgs) -> ! {
+    let mut merged: Option<Snapshot> = None;
+    for file in snapshots {
+        let res = Snapshot::read(file).and_then(|snapshot| match &mut merged {
+            Some(merged) => merged.merge(snapshot),
+            None => {
+                merged = Some(snapshot);
+                Ok(())
+            }
+        });
+        if let Err(e) = res {
+            let name = file.to_string_lossy();
+            eprintln!("Could not merge snapshot {:?}: {:?}", name, e);
+            std::process::exit(1);
+        }
+    }
+    // NOTE: At least one snapshot is required by clap.
+    let merged = merged.unwrap();
+    let report = report(output, merged.report());
+    if let Err(e) = write_report(&Mode::Report, output.format.into(), &report) {
+        eprintln!("Could not write the report: {}", e);
+        std::process::exit(1);
+    }
+    if let Some(path) = &output.snapshot {
+        write_snapshot(path, &merged);
+    }
+    match print_summary(output, &report, None, true) {
+        true => std::process::exit(0),
+        false => std::process::exit(1),
+    }
+}
+
+/// A bound of the time range given with `--since` or `--until`.
+#[derive(Copy, Clone, Debug)]
+struct TimeBound {
+    /// The number of milliseconds since the Unix epoch.
+    millis: i64,
+    /// Whether the bound was given relative to the current time.
+    relative: bool,
+}
+
+fn parse_time_bound(s: &str) -> Result<TimeBound, String> {
+    Ok(TimeBound {
+        millis: parse_time(s)?,
+        // NOTE: Only relative times start with `-`, see `parse_time`.
+        relative: s.starts_with('-'),
+    })
+}
+
+/// Parses the interval of follow mode. A zero interval would keep a core busy.
+fn parse_interval(s: &str) -> Result<Duration, String> {
+    match parse_duration(s)? {
+        interval if interval.is_zero() => Err("The interval must not be zero".to_string()),
+        interval => Ok(interval),
+    }
+}
+
+/// Parses the number of threads. Zero threads could not process anything.
+fn parse_threads(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>().map_err(|e| e.to_string())? {
+        0 => Err("The number of threads must not be zero".to_string()),
+        threads => Ok(threads),
+    }
+}
+
+/// Creates an analyzer with the options of the command line.
+fn new_analyzer(args: &Args) -> Result<Analyzer> {
+    let mut builder = Analyzer::builder()
+        .per_file(args.per_file)
+        .require_key(args.require_key)
+        .on_error(args.on_error.into())
+        .on_warning(|warning| eprintln!("{}", warning))
+        .utf8(args.utf8.into())
+        .error_samples(args.error_samples)
+        .bytes(args.bytes.into())
+        .count_compressed(args.count_compressed)
+        .mmap(!args.no_mmap)
+        .exact_percentiles(args.exact_percentiles)
+        .exact_distinct(args.exact_distinct)
+        .timestamp_format(args.timestamp_format.clone())
+        .sorted(args.sorted)
+        .schema(!matches!(args.mode, Mode::Report));
+    for key in &args.keys {
+        builder = builder.key(key.clone());
+    }
+    for filter in &args.filters {
+        builder = builder.filter(filter.clone());
+    }
+    if let Some(threads) = args.threads {
+        builder = builder.threads(threads);
+    }
+    if let Some(value) = &args.value {
+        builder = builder.value(value.clone());
+    }
+    if let Some(distinct) = &args.distinct {
+        builder = builder.distinct(distinct.clone());
+    }
+    if let Some(timestamp) = &args.timestamp {
+        builder = builder.timestamp(timestamp.clone());
+    }
+    if let Some(bucket) = &args.bucket {
+        builder = builder.bucket(bucket.clone());
+    }
+    if let Some(since) = args.since {
+        builder = builder.since(since.millis);
+    }
+    if let Some(until) = args.until {
+        builder = builder.until(until.millis);
+    }
+    builder.build()
+}
+
+/// Analyzes the files. Exits if a file cannot be processed.
+fn analyze(args: &Args, files: &[OsString]) -> Analyzer {
+    let mut analyzer = match new_analyzer(args) {
+        Ok(analyzer) => analyzer,
         Err(e) => {
-            eprintln!("Could not process file {:?}: {:?}", args.file, e);
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    if let Some(path) = &args.checkpoint {
+        resume(&mut analyzer, path, files);
+    }
+    for file in files {
+        let res = match args.follow {
+            true => follow(args, &mut analyzer, file),
+            false => analyzer.feed_file(file),
+        };
+        if let Err(e) = res {
+            eprintln!(
+                "Could not process file {:?}: {:?}",
+                file.to_string_lossy(),
+                e
+            );
             std::process::exit(1);
         }
+    }
+    analyzer
+}
+
+/// Resumes the analysis from a checkpoint if it exists and is still valid.
+fn resume(analyzer: &mut Analyzer, path: &OsStr, files: &[OsString]) {
+    if !Path::new(path).exists() {
+        return;
+    }
+    let name = path.to_string_lossy();
+    let snapshot = match Snapshot::read(path) {
+        Ok(snapshot) => snapshot,
+        Err(e) => {
+            eprintln!("Ignoring checkpoint {:?}: {:?}", name, e);
+            return;
+        }
     };
+    let files: Vec<_> = files.iter().map(|f| f.as_os_str()).collect();
+    if !analyzer.resume(snapshot, &files) {
+        eprintln!(
+            "Checkpoint {:?} is outdated, processing all files from the start",
+            name
+        );
+    }
+}
 
-    // Sort the result by type name to make the output reproducible.
-    let mut result: Vec<_> = result.into_iter().collect();
-    result.sort_by(|(l, _), (r, _)| l.cmp(r));
+/// Sorts and limits the report as requested on the command line.
+fn report(output: &OutputArgs, mut report: Report) -> Report {
+    report.sort(output.sort.into(), output.reverse);
+    if let Some(top) = output.top {
+        report.limit(top);
+    }
+    report
+}
+
+/// Follows a file until the program is interrupted with Ctrl-C.
+fn follow(args: &Args, analyzer: &mut Analyzer, file: &OsString) -> Result<()> {
+    let stop = Arc::new(AtomicBool::new(false));
+    {
+        let stop = stop.clone();
+        ctrlc::set_handler(move || stop.store(true, Relaxed))
+            .context("Could not install the Ctrl-C handler")?;
+    }
+    analyzer.follow_file(file, args.interval, &stop, |analyzer| {
+        let report = report(&args.output, analyzer.report());
+        // NOTE: On a terminal, the previous report is replaced.
+        if io::stdout().is_terminal() {
+            print!("\x1b[2J\x1b[H");
+        }
+        write_report(&args.mode, args.output.format.into(), &report)
+    })
+}
 
-    for (ty, stats) in result {
-        println!(
-            "Type {:?}: Number of Objects: {}; Total Bytes: {}",
-            ty, stats.num, stats.bytes
+/// Prints the numbers of filtered, excluded, and skipped lines on stderr. `name` is the name of
+/// the input if there are multiple inputs. `show_files` is true if the locations of skipped lines
+/// should include the names of the files.
+///
+/// Returns false if the ratio of skipped lines exceeds the maximum.
+fn print_summary(
+    output: &OutputArgs,
+    report: &Report,
+    name: Option<&str>,
+    show_files: bool,
+) -> bool {
+    let prefix = name.map(|n| format!("{}: ", n)).unwrap_or_default();
+    let lines = report.lines();
+    if report.filtered_lines() > 0 {
+        eprintln!(
+            "{}Filtered out {} of {} lines",
+            prefix,
+            report.filtered_lines(),
+            lines
+        );
+    }
+    if report.excluded_lines() > 0 {
+        eprintln!(
+            "{}Excluded {} of {} lines outside of the time range",
+            prefix,
+            report.excluded_lines(),
+            lines
+        );
+    }
+    if report.invalid_utf8_lines() > 0 {
+        eprintln!(
+            "{}{} of {} lines are not valid UTF-8",
+            prefix,
+            report.invalid_utf8_lines(),
+            lines
         );
     }
+    let skipped = report.skipped_lines();
+    if skipped > 0 {
+        print_error_summary(report.errors(), lines, show_files);
+        let ratio = skipped as f64 / lines as f64;
+        if ratio > output.max_error_ratio {
+            eprintln!(
+                "{}The ratio of skipped lines ({}) exceeds the maximum ({})",
+                prefix, ratio, output.max_error_ratio
+            );
+            return false;
+        }
+    }
+    true
 }
 
-fn process_file(file: &OsString) -> Result<HashMap<String, TypeData>> {
-    let mut result = HashMap::new();
-    let file = File::open(file).context("Could not open the file")?;
-    let file = BufReader::new(file);
-    for (n, line) in file.lines().enumerate() {
-        process_line(&mut result, line)
-            .with_context(|| format!("Could not process line number {}", n + 1))?;
-    }
-    Ok(result)
-}
-
-fn process_line(stats: &mut HashMap<String, TypeData>, line: io::Result<String>) -> Result<()> {
-    let line = line.context("Could not read from the file")?;
-    let obj: JsonObject =
-        serde_json::from_str(&line).with_context(|| format!("Could not parse `{}`", line))?;
-    let data = stats.entry(obj.ty).or_default();
-    // NOTE: These fields cannot realistically overflow. Even if each byte took only 1ns to process,
-    // it would still take more than 300 years before data.bytes overflows. I assume that serde_json
-    // is much slower than that. Furthermore, the only way for us to process so many bytes is if
-    // the input file refers to a pipe (or some weird FUSE file system). I'm using `checked_add` only
-    // because this is an exercise and to demonstrate that I'm aware of such issues.
-    data.num += 1;
-    data.bytes = data
-        .bytes
-        .checked_add(line.len() as u64)
-        .ok_or_else(|| anyhow!("Total number of bytes processed exceeded 2^64"))?;
+fn write_report(mode: &Mode, format: Format, report: &Report) -> Result<()> {
+    let mut stdout = BufWriter::new(io::stdout().lock());
+    match mode {
+        Mode::Schema(schema) if schema.json_schema => report.write_json_schema(&mut stdout)?,
+        Mode::Schema(_) => report.write_schema(format, &mut stdout)?,
+        Mode::Report | Mode::Diff(_) => report.write(format, &mut stdout)?,
+    }
+    stdout.flush()?;
     Ok(())
 }
+
+/// Writes a snapshot to a file. Exits if the snapshot cannot be written.
+fn write_snapshot(path: &OsStr, snapshot: &Snapshot) {
+    // NOTE: The snapshot is written to a temporary file first so that an interrupted run does
+    // not leave a truncated checkpoint behind.
+    let mut tmp = path.to_owned();
+    tmp.push(".tmp");
+    let res = File::create(&tmp)
+        .context("Could not create the file")
+        .and_then(|file| snapshot.write(BufWriter::new(file)))
+        .and_then(|()| fs::rename(&tmp, path).context("Could not replace the file"));
+    if let Err(e) = res {
+        let name = path.to_string_lossy();
+        eprintln!("Could not write snapshot {:?}: {:?}", name, e);
+        std::process::exit(1);
+    }
+}
+
+fn print_error_summary(errors: &ErrorStats, lines: u64, show_files: bool) {
+    let mut stderr = io::stderr().lock();
+    let skipped = errors.total();
+    let _ = writeln!(
+        stderr,
+        "Skipped {} of {} lines ({:.2}%):",
+        skipped,
+        lines,
+        100.0 * skipped as f64 / lines as f64
+    );
+    for kind in ErrorKind::ALL {
+        let (num, samples) = errors.get(kind);
+        if num == 0 {
+            continue;
+        }
+        if samples.is_empty() {
+            let _ = writeln!(stderr, "    {}: {}", kind, num);
+            continue;
+        }
+        let samples: Vec<_> = samples
+            .iter()
+            .map(|l| match show_files {
+                true => l.to_string(),
+                false => l.line.to_string(),
+            })
+            .collect();
+        let ellipsis = if num > samples.len() as u64 {
+            ", ..."
+        } else {
+            ""
+        };
+        let _ = writeln!(
+            stderr,
+            "    {}: {} (line{} {}{})",
+            kind,
+            num,
+            if num > 1 { "s" } else { "" },
+            samples.join(", "),
+            ellipsis
+        );
+    }
+}
@@ -0,0 +1,88 @@
+use serde_json::Value;
+use std::fmt;
+use std::str::FromStr;
+
+/// The location of a field in a json value.
+///
+/// Paths starting with `/` are interpreted as JSON Pointers (RFC 6901). All other paths are
+/// interpreted as a sequence of object keys separated by `.`. In both cases, a segment that is a
+/// decimal number can also be used to index into an array.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FieldPath {
+    /// The path as written by the user.
+    source: String,
+    segments: Vec<String>,
+}
+
+impl FieldPath {
+    /// Returns the value at this path or `None` if the value does not contain the field.
+    pub fn lookup<'a>(&self, mut value: &'a Value) -> Option<&'a Value> {
+        for segment in &self.segments {
+            value = match value {
+                Value::Object(o) => o.get(segment)?,
+                Value::Array(a) => a.get(parse_index(segment)?)?,
+                _ => return None,
+            };
+        }
+        Some(value)
+    }
+
+    /// The object keys or array indices leading to the field.
+    pub fn segments(&self) -> &[String] {
+        &self.segments
+    }
+}
+
+/// Parses an array index as described in RFC 6901. Leading zeros are not allowed.
+pub fn parse_index(s: &str) -> Option<usize> {
+    if s.starts_with('+') || (s.starts_with('0') && s.len() > 1) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+impl FromStr for FieldPath {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let segments = if let Some(pointer) = s.strip_prefix('/') {
+            pointer
+                .split('/')
+                .map(unescape_pointer_segment)
+                .collect::<Result<_, _>>()?
+        } else if s.is_empty() {
+            // NOTE: The empty string is a valid JSON Pointer referring to the whole value but
+            // grouping by the whole entry is almost certainly a mistake.
+            return Err("The path must not be empty".to_string());
+        } else {
+            s.split('.').map(|s| s.to_string()).collect()
+        };
+        Ok(Self {
+            source: s.to_string(),
+            segments,
+        })
+    }
+}
+
+fn unescape_pointer_segment(s: &str) -> Result<String, String> {
+    let mut res = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '~' {
+            match chars.next() {
+                Some('0') => res.push('~'),
+                Some('1') => res.push('/'),
+                _ => return Err(format!("Invalid escape sequence in JSON Pointer `{}`", s)),
+            }
+        } else {
+            res.push(c);
+        }
+    }
+    Ok(res)
+}
+
+impl fmt::Display for FieldPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
@@ -0,0 +1,161 @@
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Processes a sequence of items on multiple threads.
+///
+/// `next` is called on a dedicated thread to produce the items until it returns `None`. Each item
+/// is passed to `process` on one of `threads` worker threads together with a state that is
+/// created by `init` once per worker thread. The results are passed to `merge`
+/// on the calling thread in the order in which the items were produced. Therefore, the result is
+/// the same as if all items had been processed sequentially.
+///
+/// The first error (in the order of the items) aborts the processing and is returned.
+///
+/// If `threads` is at most 1, everything happens on the calling thread.
+pub fn run<T, S, R>(
+    threads: usize,
+    mut next: impl FnMut() -> Result<Option<T>> + Send,
+    init: impl Fn() -> S + Sync,
+    process: impl Fn(&mut S, T) -> Result<R> + Sync,
+    mut merge: impl FnMut(R) -> Result<()>,
+) -> Result<()>
+where
+    T: Send,
+    R: Send,
+{
+    if threads <= 1 {
+        let mut state = init();
+        while let Some(item) = next()? {
+            merge(process(&mut state, item)?)?;
+        }
+        return Ok(());
+    }
+    thread::scope(|s| {
+        // NOTE: The number of items in flight is bounded to limit the memory usage if the
+        // workers are slower than the reader. This includes the results that wait for an earlier
+        // result before they can be merged: The reader only produces an item if fewer than
+        // `window` of the previous items have not been merged yet. The merging thread sends
+        // a token to the reader for each merged item.
+        let window = 4 * threads as u64;
+        let (item_tx, item_rx) = mpsc::sync_channel(2 * threads);
+        let (result_tx, result_rx) = mpsc::channel();
+        let (merged_tx, merged_rx) = mpsc::channel();
+        s.spawn(move || {
+            for idx in 0u64.. {
+                // NOTE: Receiving fails if the calling thread has stopped merging because of an
+                // error.
+                if idx >= window && merged_rx.recv().is_err() {
+                    break;
+                }
+                let item = next().transpose();
+                let done = !matches!(item, Some(Ok(_)));
+                // NOTE: Sending fails if all workers have exited because of an error.
+                if let Some(item) = item {
+                    if item_tx.send((idx, item)).is_err() {
+                        break;
+                    }
+                }
+                if done {
+                    break;
+                }
+            }
+        });
+        // NOTE: The receiver is dropped once all workers have exited. This unblocks the reader.
+        let item_rx = Arc::new(Mutex::new(item_rx));
+        for _ in 0..threads {
+            let item_rx = item_rx.clone();
+            let result_tx = result_tx.clone();
+            let (init, process) = (&init, &process);
+            s.spawn(move || {
+                let mut state = init();
+                loop {
+                    let item = item_rx.lock().unwrap().recv();
+                    let (idx, item) = match item {
+                        Ok(item) => item,
+                        Err(_) => break,
+                    };
+                    let result = item.and_then(|item| process(&mut state, item));
+                    // NOTE: Sending fails if the calling thread has stopped merging because of an
+                    // error.
+                    if result_tx.send((idx, result)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(item_rx);
+        drop(result_tx);
+        let mut pending = BTreeMap::new();
+        let mut next_idx = 0;
+        for (idx, result) in result_rx {
+            pending.insert(idx, result);
+            while let Some(result) = pending.remove(&next_idx) {
+                next_idx += 1;
+                merge(result?)?;
+                let _ = merged_tx.send(());
+            }
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::bail;
+    use std::sync::atomic::{AtomicU64, Ordering::Relaxed};
+    use std::time::Duration;
+
+    #[test]
+    fn unmerged_items_are_bounded() {
+        let threads = 3;
+        let merged = AtomicU64::new(0);
+        let mut produced = 0;
+        let mut results = vec![];
+        run(
+            threads,
+            || {
+                assert!(produced - merged.load(Relaxed) < 4 * threads as u64);
+                produced += 1;
+                Ok((produced <= 1000).then_some(produced))
+            },
+            || (),
+            |_, item| {
+                // NOTE: The first item is slow so that all later results have to wait for it.
+                if item == 1 {
+                    thread::sleep(Duration::from_millis(50));
+                }
+                Ok(item)
+            },
+            |item| {
+                results.push(item);
+                merged.fetch_add(1, Relaxed);
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert_eq!(results, (1..=1000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn first_error_is_returned() {
+        let mut produced = 0;
+        let result = run(
+            4,
+            || {
+                produced += 1;
+                Ok(Some(produced))
+            },
+            || (),
+            |_, item| match item % 100 {
+                0 => bail!("item {}", item),
+                _ => Ok(item),
+            },
+            |_| Ok(()),
+        );
+        assert_eq!(result.unwrap_err().to_string(), "item 100");
+    }
+}
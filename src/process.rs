@@ -0,0 +1,577 @@
+use crate::analyzer::Options;
+use crate::distinct;
+use crate::errors::{ErrorKind, ErrorStats, LineError, Location, OnError, Utf8Mode};
+use crate::filter::Filter;
+use crate::input::Chunk;
+use crate::key::{GroupKey, Key};
+use crate::path::FieldPath;
+use crate::scan::{Scanner, Unsupported};
+use crate::stats::{Stats, TypeData};
+use anyhow::{anyhow, Context, Result};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::Arc;
+
+/// Returns the number of lines in `data`, which must start at a line boundary.
+pub fn count_lines(data: &[u8]) -> u64 {
+    let incomplete = !data.is_empty() && data.last() != Some(&b'\n');
+    memchr::memchr_iter(b'\n', data).count() as u64 + incomplete as u64
+}
+
+/// Returns the timestamp of a line or `None` if it does not contain a valid timestamp.
+pub fn line_timestamp(options: &Options, line: &[u8]) -> Option<i64> {
+    let field = options.timestamp.as_ref()?;
+    let value: Value = serde_json::from_slice(strip_line_terminator(line)).ok()?;
+    options.timestamp_format.parse(field.lookup(&value)?).ok()
+}
+
+/// Creates a scanner for the key fields followed by the timestamp field, the value field, the
+/// distinct field, and the fields of the filters.
+pub fn new_scanner(options: &Options) -> Scanner {
+    let mut paths = options.keys.clone();
+    paths.extend(options.timestamp.clone());
+    paths.extend(options.value.clone());
+    paths.extend(options.distinct.clone());
+    paths.extend(options.filters.iter().flat_map(|f| f.fields()).cloned());
+    Scanner::new(&paths)
+}
+
+/// The index of the value field in the paths of the scanner.
+fn value_field_index(options: &Options) -> usize {
+    options.keys.len() + options.timestamp.is_some() as usize
+}
+
+/// The index of the distinct field in the paths of the scanner.
+fn distinct_field_index(options: &Options) -> usize {
+    value_field_index(options) + options.value.is_some() as usize
+}
+
+/// The index of the first field of the filters in the paths of the scanner.
+fn filter_fields_start(options: &Options) -> usize {
+    distinct_field_index(options) + options.distinct.is_some() as usize
+}
+
+/// The state of a thread processing the chunks of a file.
+pub struct Worker {
+    options: Arc<Options>,
+    /// The name of the file.
+    name: String,
+    /// The key of the file if the report is broken down by file.
+    file_key: Option<Key>,
+    /// The scanner created by [`new_scanner`].
+    scanner: Arc<Scanner>,
+    /// Reused buffer for the locations of the fields in the current line.
+    ranges: Vec<Option<Range<usize>>>,
+    /// Reused buffer for the raw key of the current line.
+    raw_key: Vec<u8>,
+    /// Maps the raw bytes of the key fields (as located by the scanner) to indices in
+    /// `group_keys`. This avoids parsing the key fields of most lines and hashing group keys.
+    raw_keys: HashMap<Vec<u8>, usize>,
+    /// The parsed group keys of the entries in `raw_keys`.
+    group_keys: Vec<GroupKey>,
+    /// Reused buffer for the values of the fields of the filters in the current line.
+    filter_values: Vec<Option<Value>>,
+}
+
+impl Worker {
+    pub fn new(options: &Arc<Options>, scanner: &Arc<Scanner>, name: &str) -> Self {
+        Self {
+            options: options.clone(),
+            name: name.to_string(),
+            file_key: options
+                .per_file
+                .then(|| Key(Value::String(name.to_string()))),
+            scanner: scanner.clone(),
+            ranges: vec![],
+            raw_key: vec![],
+            raw_keys: HashMap::new(),
+            group_keys: vec![],
+            filter_values: vec![],
+        }
+    }
+}
+
+/// The maximum number of entries in `Worker::raw_keys`. Once this is exceeded, the map is cleared
+/// before the next chunk.
+const MAX_RAW_KEYS: usize = 1 << 16;
+
+/// The statistics of a chunk that is being processed.
+struct ChunkStats {
+    stats: Stats,
+    /// The statistics of the entries that were processed by the fast path, indexed like
+    /// `Worker::group_keys`.
+    fast: Vec<Option<TypeData>>,
+    /// Like `fast` but broken down by the start of the time bucket.
+    fast_buckets: HashMap<(usize, i64), TypeData>,
+    /// The entries of `fast` and `fast_buckets` in the order in which they were created.
+    fast_order: Vec<(usize, Option<i64>)>,
+}
+
+/// Processes the lines in a chunk.
+///
+/// Returns the statistics of the chunk and the warnings that should be printed.
+pub fn process_chunk(worker: &mut Worker, chunk: &Chunk) -> Result<(Stats, Vec<String>)> {
+    let options = worker.options.clone();
+    let name = worker.name.clone();
+    if worker.group_keys.len() > MAX_RAW_KEYS {
+        worker.raw_keys.clear();
+        worker.group_keys.clear();
+    }
+    let mut stats = ChunkStats {
+        stats: Stats::new(ErrorStats::new(options.error_samples)),
+        fast: vec![],
+        fast_buckets: HashMap::new(),
+        fast_order: vec![],
+    };
+    let mut warnings = vec![];
+    let mut start = 0;
+    let ends = memchr::memchr_iter(b'\n', &chunk.data)
+        .map(|n| n + 1)
+        .chain(Some(chunk.data.len()));
+    for (line_number, end) in (chunk.first_line..).zip(ends) {
+        if start == end {
+            // NOTE: The chunk ends with a line terminator.
+            break;
+        }
+        let line = &chunk.data[start..end];
+        start = end;
+        stats.stats.lines += 1;
+        stats.stats.read_bytes += line.len() as u64;
+        stats.stats.input_bytes += line.len() as u64;
+        let res = process_line(&mut stats, worker, &options, line)
+            .with_context(|| format!("Could not process line number {}", line_number))?;
+        if let Err(e) = res {
+            stats.stats.other_bytes += line.len() as u64;
+            match options.on_error {
+                OnError::Fail => {
+                    return Err(e.error)
+                        .with_context(|| format!("Could not process line number {}", line_number));
+                }
+                OnError::Warn => {
+                    warnings.push(format!(
+                        "Skipping line number {} of {}: {:#}",
+                        line_number, name, e.error
+                    ));
+                }
+                OnError::Skip => {}
+            }
+            stats.stats.errors.record(e.kind, || Location {
+                file: name.to_string(),
+                line: line_number,
+            });
+        }
+    }
+    // NOTE: The indices of the group keys depend on the lines processed by the worker before
+    // this chunk. The entries are merged in the order in which they first occurred in the chunk
+    // instead so that the floating-point statistics do not depend on the number of threads.
+    let ChunkStats {
+        mut stats,
+        mut fast,
+        mut fast_buckets,
+        fast_order,
+    } = stats;
+    for (idx, start) in fast_order {
+        let mut ty = worker.group_keys[idx].clone();
+        let data = match (&options.bucket, start) {
+            (Some(buckets), Some(start)) => {
+                ty.push(Some(buckets.key(start)));
+                fast_buckets.remove(&(idx, start))
+            }
+            _ => fast[idx].take(),
+        };
+        if let Some(data) = data {
+            stats.types.entry(ty).or_default().merge(&data);
+        }
+    }
+    Ok((stats, warnings))
+}
+
+/// Scales the sizes of the entries and the bytes of the other lines by
+/// `numerator / denominator`.
+///
+/// The results are rounded such that the total is scaled exactly.
+pub fn scale_bytes(stats: &mut Stats, numerator: u64, denominator: u64) {
+    // NOTE: The types are sorted to make the rounding reproducible.
+    let mut types: Vec<_> = stats.types.iter_mut().collect();
+    types.sort_by_key(|(k, _)| *k);
+    let sizes = types
+        .into_iter()
+        .map(|(_, data)| &mut data.bytes)
+        .chain(Some(&mut stats.other_bytes));
+    let scale = |n: u128| (n * numerator as u128 / denominator.max(1) as u128) as u64;
+    let mut total = 0;
+    for bytes in sizes {
+        let start = scale(total);
+        total += *bytes as u128;
+        *bytes = scale(total) - start;
+    }
+}
+
+fn missing_key_error(keys: &[FieldPath]) -> LineError {
+    let e = match keys {
+        [key] => anyhow!("The entry does not contain the field `{}`", key),
+        _ => anyhow!("The entry does not contain any of the key fields"),
+    };
+    LineError::new(ErrorKind::MissingKey, e)
+}
+
+fn missing_timestamp_error(field: &FieldPath) -> anyhow::Error {
+    anyhow!("The entry does not contain the timestamp field `{}`", field)
+}
+
+/// Extracts the fields of an entry without parsing the whole line.
+///
+/// This is the fast path of `process_line`. See [`Scanner`] for the cases in which this fails.
+fn scan_entry(worker: &mut Worker, options: &Options, line: &[u8]) -> Result<Entry, Unsupported> {
+    worker.scanner.scan(line, &mut worker.ranges)?;
+    if !options.filters.is_empty() {
+        worker.filter_values.clear();
+        for range in &worker.ranges[filter_fields_start(options)..] {
+            let value = match range {
+                Some(range) => {
+                    Some(serde_json::from_slice(&line[range.clone()]).map_err(|_| Unsupported)?)
+                }
+                None => None,
+            };
+            worker.filter_values.push(value);
+        }
+        let values = &worker.filter_values;
+        if !matches_filters(&options.filters, |i| values[i].as_ref()) {
+            return Ok(Entry::Filtered);
+        }
+    }
+    let key_ranges = &worker.ranges[..options.keys.len()];
+    if options.require_key && key_ranges.iter().all(|r| r.is_none()) {
+        return Ok(Entry::MissingKey);
+    }
+    let idx = group_key_index(worker, line)?;
+    let timestamp =
+        options
+            .timestamp
+            .as_ref()
+            .map(|field| match worker.ranges[options.keys.len()].clone() {
+                Some(range) => options.timestamp_format.parse_raw(&line[range]),
+                None => Err(missing_timestamp_error(field)),
+            });
+    // NOTE: The value is parsed by serde_json so that it is the same as in `parse_entry`.
+    let value = match options.value {
+        Some(_) => worker.ranges[value_field_index(options)]
+            .clone()
+            .and_then(|range| serde_json::from_slice(&line[range]).ok()),
+        None => None,
+    };
+    let distinct = match options.distinct {
+        Some(_) => match worker.ranges[distinct_field_index(options)].clone() {
+            Some(range) => Some(distinct::hash_raw(&line[range]).ok_or(Unsupported)?),
+            None => None,
+        },
+        None => None,
+    };
+    Ok(Entry::Included {
+        group: Group::Fast(idx),
+        timestamp,
+        value,
+        distinct,
+        entry: None,
+    })
+}
+
+/// Extracts the fields of an entry by parsing the line. Only the fields are built unless the
+/// schema is collected.
+fn parse_entry(worker: &Worker, options: &Options, line: &str) -> Result<Entry, LineError> {
+    let parsed = match options.schema {
+        true => serde_json::from_str(line),
+        false => worker.scanner.parse(line),
+    };
+    let obj: Value = match parsed {
+        Ok(v) => v,
+        Err(e) => {
+            let e = anyhow!(e).context(format!("Could not parse `{}`", line));
+            return Err(LineError::new(ErrorKind::InvalidJson, e));
+        }
+    };
+    let filter_values: Vec<_> = options
+        .filters
+        .iter()
+        .flat_map(|f| f.fields())
+        .map(|f| f.lookup(&obj))
+        .collect();
+    if !matches_filters(&options.filters, |i| filter_values[i]) {
+        return Ok(Entry::Filtered);
+    }
+    let values: Vec<_> = options.keys.iter().map(|k| k.lookup(&obj)).collect();
+    if options.require_key && values.iter().all(|v| v.is_none()) {
+        return Ok(Entry::MissingKey);
+    }
+    let timestamp = options
+        .timestamp
+        .as_ref()
+        .map(|field| match field.lookup(&obj) {
+            Some(value) => options.timestamp_format.parse(value),
+            None => Err(missing_timestamp_error(field)),
+        });
+    let ty: GroupKey = worker
+        .file_key
+        .as_ref()
+        .map(|k| Some(k.clone()))
+        .into_iter()
+        .chain(values.iter().map(|v| v.map(|v| Key(v.clone()))))
+        .collect();
+    let value = options
+        .value
+        .as_ref()
+        .and_then(|field| field.lookup(&obj))
+        .and_then(|value| value.as_f64());
+    let distinct = options
+        .distinct
+        .as_ref()
+        .and_then(|field| field.lookup(&obj))
+        .map(distinct::hash_value);
+    Ok(Entry::Included {
+        group: Group::Parsed(ty),
+        timestamp,
+        value,
+        distinct,
+        entry: options.schema.then_some(obj),
+    })
+}
+
+/// Returns whether an entry matches all filters. `value(i)` returns the value of the i-th field of
+/// the concatenated fields of all filters.
+fn matches_filters<'a>(filters: &[Filter], value: impl Fn(usize) -> Option<&'a Value>) -> bool {
+    let mut offset = 0;
+    filters.iter().all(|filter| {
+        let matches = filter.matches(&|i| value(offset + i));
+        offset += filter.fields().len();
+        matches
+    })
+}
+
+/// Determines the group key of a line that has been scanned.
+///
+/// Returns the index of the group key in `Worker::group_keys`.
+fn group_key_index(worker: &mut Worker, line: &[u8]) -> Result<usize, Unsupported> {
+    let ranges = &worker.ranges[..worker.options.keys.len()];
+    worker.raw_key.clear();
+    for range in ranges {
+        match range {
+            Some(range) => {
+                worker.raw_key.push(1);
+                worker
+                    .raw_key
+                    .extend_from_slice(&(range.len() as u64).to_le_bytes());
+                worker.raw_key.extend_from_slice(&line[range.clone()]);
+            }
+            None => worker.raw_key.push(0),
+        }
+    }
+    if let Some(&idx) = worker.raw_keys.get(&worker.raw_key) {
+        return Ok(idx);
+    }
+    let mut ty = GroupKey::new();
+    ty.extend(worker.file_key.as_ref().map(|k| Some(k.clone())));
+    for range in ranges {
+        let value = match range {
+            Some(range) => Some(Key(
+                serde_json::from_slice(&line[range.clone()]).map_err(|_| Unsupported)?
+            )),
+            None => None,
+        };
+        ty.push(value);
+    }
+    let idx = worker.group_keys.len();
+    worker.group_keys.push(ty);
+    worker.raw_keys.insert(worker.raw_key.clone(), idx);
+    Ok(idx)
+}
+
+/// Removes a trailing `\n` or `\r\n` in the same way as `BufRead::lines`.
+pub fn strip_line_terminator(line: &[u8]) -> &[u8] {
+    match line {
+        [line @ .., b'\r', b'\n'] => line,
+        [line @ .., b'\n'] => line,
+        _ => line,
+    }
+}
+
+/// Decodes a line that is not valid UTF-8. Invalid bytes in json strings are replaced by `\u00XX`
+/// escape sequences, i.e. they are decoded as Latin-1 characters. Other invalid bytes cannot be
+/// part of valid json and are replaced by U+FFFD.
+fn escape_invalid_utf8(line: &[u8]) -> String {
+    let mut decoded = String::with_capacity(line.len() + line.len() / 2);
+    let mut in_string = false;
+    let mut escaped = false;
+    for chunk in line.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' if in_string => escaped = true,
+                '"' => in_string = !in_string,
+                _ => {}
+            }
+            decoded.push(c);
+        }
+        for &b in chunk.invalid() {
+            // NOTE: An escape sequence must not start with an invalid byte.
+            match in_string && !escaped {
+                true => decoded.push_str(&format!("\\u{:04x}", b)),
+                false => decoded.push(char::REPLACEMENT_CHARACTER),
+            }
+            escaped = false;
+        }
+    }
+    decoded
+}
+
+/// The group of an entry.
+enum Group {
+    /// The index of the group key in `Worker::group_keys` if the fast path was used.
+    Fast(usize),
+    /// The group key if the entry had to be parsed.
+    Parsed(GroupKey),
+}
+
+/// The fields extracted from an entry.
+enum Entry {
+    /// The entry does not match the filters.
+    Filtered,
+    /// The entry does not contain any of the key fields and they are required.
+    MissingKey,
+    Included {
+        group: Group,
+        /// The timestamp if a timestamp field is configured.
+        timestamp: Option<Result<i64>>,
+        /// The value of the value field if it is configured and a number.
+        value: Option<f64>,
+        /// The hash of the value of the distinct field if it is configured and present.
+        distinct: Option<u64>,
+        /// The parsed entry if the schema is collected.
+        entry: Option<Value>,
+    },
+}
+
+/// Processes a single line including its terminator.
+///
+/// Returns an error in the outer result if the analysis cannot continue and an error in the inner
+/// result if only this line could not be processed.
+fn process_line(
+    stats: &mut ChunkStats,
+    worker: &mut Worker,
+    options: &Options,
+    raw: &[u8],
+) -> Result<Result<(), LineError>> {
+    let content = strip_line_terminator(raw);
+    let decoded;
+    let line = match std::str::from_utf8(content) {
+        Ok(l) => l,
+        Err(e) => {
+            stats.stats.invalid_utf8 += 1;
+            decoded = match options.utf8 {
+                Utf8Mode::Strict => {
+                    let e = anyhow!(e).context("The line is not valid UTF-8");
+                    return Ok(Err(LineError::new(ErrorKind::InvalidUtf8, e)));
+                }
+                Utf8Mode::Lossy => String::from_utf8_lossy(content).into_owned(),
+                Utf8Mode::Escape => escape_invalid_utf8(content),
+            };
+            &decoded
+        }
+    };
+    // NOTE: The schema requires the whole entry to be parsed.
+    let scanned = match options.schema {
+        true => Err(Unsupported),
+        false => scan_entry(worker, options, line.as_bytes()),
+    };
+    let entry = match scanned {
+        Ok(entry) => entry,
+        Err(Unsupported) => match parse_entry(worker, options, line) {
+            Ok(entry) => entry,
+            Err(e) => return Ok(Err(e)),
+        },
+    };
+    let (group, timestamp, value, distinct, entry) = match entry {
+        Entry::Filtered => {
+            stats.stats.filtered += 1;
+            stats.stats.other_bytes += raw.len() as u64;
+            return Ok(Ok(()));
+        }
+        Entry::MissingKey => return Ok(Err(missing_key_error(&options.keys))),
+        Entry::Included {
+            group,
+            timestamp,
+            value,
+            distinct,
+            entry,
+        } => (group, timestamp, value, distinct, entry),
+    };
+    let timestamp = match timestamp.transpose() {
+        Ok(t) => t,
+        Err(e) => return Ok(Err(LineError::new(ErrorKind::InvalidTimestamp, e))),
+    };
+    if let Some(timestamp) = timestamp {
+        let after_since = options.since.is_none_or(|since| timestamp >= since);
+        let before_until = options.until.is_none_or(|until| timestamp < until);
+        if !after_since || !before_until {
+            stats.stats.excluded += 1;
+            stats.stats.other_bytes += raw.len() as u64;
+            return Ok(Ok(()));
+        }
+    }
+    let bucket = match (&options.bucket, timestamp) {
+        (Some(buckets), Some(timestamp)) => match buckets.start(timestamp) {
+            Some(start) => Some((buckets, start)),
+            None => {
+                let e = anyhow!("The timestamp is out of range");
+                return Ok(Err(LineError::new(ErrorKind::InvalidTimestamp, e)));
+            }
+        },
+        _ => None,
+    };
+    let data = match (group, bucket) {
+        (Group::Fast(idx), None) => {
+            if stats.fast.len() <= idx {
+                stats.fast.resize_with(idx + 1, || None);
+            }
+            let order = &mut stats.fast_order;
+            stats.fast[idx].get_or_insert_with(|| {
+                order.push((idx, None));
+                TypeData::default()
+            })
+        }
+        (Group::Fast(idx), Some((_, start))) => {
+            let order = &mut stats.fast_order;
+            stats.fast_buckets.entry((idx, start)).or_insert_with(|| {
+                order.push((idx, Some(start)));
+                TypeData::default()
+            })
+        }
+        (Group::Parsed(mut ty), bucket) => {
+            ty.extend(bucket.map(|(buckets, start)| Some(buckets.key(start))));
+            stats.stats.types.entry(ty).or_default()
+        }
+    };
+    // NOTE: These fields cannot realistically overflow. Even if each byte took only 1ns to process,
+    // it would still take more than 300 years before data.bytes overflows. I assume that serde_json
+    // is much slower than that. Furthermore, the only way for us to process so many bytes is if
+    // the input file refers to a pipe (or some weird FUSE file system). I'm using `checked_add` only
+    // because this is an exercise and to demonstrate that I'm aware of such issues.
+    let bytes = options.bytes.count(raw);
+    stats.stats.terminator_bytes += raw.len() as u64 - bytes;
+    data.num += 1;
+    data.bytes = data
+        .bytes
+        .checked_add(bytes)
+        .ok_or_else(|| anyhow!("Total number of bytes processed exceeded 2^64"))?;
+    data.sizes.add(bytes);
+    if let Some(value) = value {
+        data.values.add(value, options.exact_percentiles);
+    }
+    if let Some(hash) = distinct {
+        data.distinct.add(hash, options.exact_distinct);
+    }
+    if let Some(entry) = entry {
+        data.schema.add(&entry);
+    }
+    Ok(Ok(()))
+}
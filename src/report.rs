@@ -0,0 +1,1345 @@
+use crate::errors::ErrorStats;
+use crate::key::{display_component, GroupKey, Key};
+use crate::stats::{size_bucket_range, ByteMode, SizeStats, Stats, TypeData};
+use crate::table::{Align, Table};
+use crate::values::{ValueStats, QUANTILES};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::{BTreeSet, HashMap};
+use std::io;
+use std::io::Write;
+use std::ops::Range;
+
+/// The version of the machine-readable output formats.
+///
+/// This is incremented whenever a field is removed or its meaning changes. Adding new fields does
+/// not change the version.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// The format of the report.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Format {
+    /// An aligned table
+    Human,
+    /// A single json document
+    Json,
+    /// Comma-separated values with a header row
+    Csv,
+    /// Tab-separated values with a header row
+    Tsv,
+    /// One json object per line
+    Ndjson,
+}
+
+/// The order of the rows of a report.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SortKey {
+    /// By the values of the key fields
+    Name,
+    /// By the number of objects, largest first
+    Count,
+    /// By the total bytes, largest first
+    Bytes,
+    /// By the average size of the objects, largest first
+    AvgSize,
+}
+
+/// The sorted results of an analysis.
+pub struct Report {
+    key_fields: Vec<String>,
+    /// Whether the last key field is the start of a time bucket.
+    time_buckets: bool,
+    /// The name of the field selected with `--value`.
+    value_field: Option<String>,
+    /// The name of the field selected with `--distinct`.
+    distinct_field: Option<String>,
+    rows: Vec<(GroupKey, TypeData)>,
+    /// The number of types that were removed by [`Report::limit`] and their combined statistics.
+    other: Option<(usize, TypeData)>,
+    total: TypeData,
+    lines: u64,
+    excluded: u64,
+    filtered: u64,
+    invalid_utf8: u64,
+    errors: ErrorStats,
+    /// The bytes counted in `TypeData::bytes`.
+    byte_mode: ByteMode,
+    /// Whether `TypeData::bytes` refers to the compressed size of compressed inputs.
+    count_compressed: bool,
+    read_bytes: u64,
+    input_bytes: u64,
+    terminator_bytes: u64,
+    other_bytes: u64,
+}
+
+impl Report {
+    /// Creates a report. `key_fields` contains the names of the components of the group keys.
+    /// If `time_buckets` is true, the last component is the start of a time bucket.
+    pub(crate) fn new(
+        key_fields: Vec<String>,
+        time_buckets: bool,
+        value_field: Option<String>,
+        distinct_field: Option<String>,
+        byte_mode: ByteMode,
+        count_compressed: bool,
+        stats: &Stats,
+    ) -> Self {
+        // Sort the result by type to make the output reproducible.
+        let mut rows: Vec<_> = stats
+            .types
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        rows.sort_by(|(l, _), (r, _)| l.cmp(r));
+        let mut total = TypeData::default();
+        for (_, data) in &rows {
+            total.merge(data);
+        }
+        Self {
+            key_fields,
+            time_buckets,
+            value_field,
+            distinct_field,
+            rows,
+            other: None,
+            total,
+            lines: stats.lines,
+            excluded: stats.excluded,
+            filtered: stats.filtered,
+            invalid_utf8: stats.invalid_utf8,
+            errors: stats.errors.clone(),
+            byte_mode,
+            count_compressed,
+            read_bytes: stats.read_bytes,
+            input_bytes: stats.input_bytes,
+            terminator_bytes: stats.terminator_bytes,
+            other_bytes: stats.other_bytes,
+        }
+    }
+
+    /// The names of the components of the group keys.
+    pub fn key_fields(&self) -> &[String] {
+        &self.key_fields
+    }
+
+    /// The name of the value field.
+    pub fn value_field(&self) -> Option<&str> {
+        self.value_field.as_deref()
+    }
+
+    /// The name of the distinct field.
+    pub fn distinct_field(&self) -> Option<&str> {
+        self.distinct_field.as_deref()
+    }
+
+    /// The types in the order of the report.
+    pub fn types(&self) -> &[(GroupKey, TypeData)] {
+        &self.rows
+    }
+
+    /// The number of types removed by [`Report::limit`] and their combined statistics.
+    pub fn other(&self) -> Option<(usize, &TypeData)> {
+        self.other.as_ref().map(|(n, data)| (*n, data))
+    }
+
+    /// The combined statistics of all types.
+    pub fn total(&self) -> &TypeData {
+        &self.total
+    }
+
+    /// The number of lines that were read, including the ones that were not counted.
+    pub fn lines(&self) -> u64 {
+        self.lines
+    }
+
+    /// The number of lines that were excluded because their timestamps are outside of the time
+    /// range.
+    pub fn excluded_lines(&self) -> u64 {
+        self.excluded
+    }
+
+    /// The number of lines that were excluded because they do not match the filters.
+    pub fn filtered_lines(&self) -> u64 {
+        self.filtered
+    }
+
+    /// The number of lines that are not valid UTF-8. Depending on [`AnalyzerBuilder::utf8`],
+    /// they were either decoded or skipped.
+    ///
+    /// [`AnalyzerBuilder::utf8`]: crate::AnalyzerBuilder::utf8
+    pub fn invalid_utf8_lines(&self) -> u64 {
+        self.invalid_utf8
+    }
+
+    /// The number of lines that could not be processed.
+    pub fn skipped_lines(&self) -> u64 {
+        self.errors.total()
+    }
+
+    /// The lines that could not be processed.
+    pub fn errors(&self) -> &ErrorStats {
+        &self.errors
+    }
+
+    /// The bytes of a line that are counted as the size of the entry.
+    pub fn byte_mode(&self) -> ByteMode {
+        self.byte_mode
+    }
+
+    /// The number of decompressed bytes that were read, including line terminators.
+    pub fn read_bytes(&self) -> u64 {
+        self.read_bytes
+    }
+
+    /// The size of the data that was read as stored. This differs from [`Report::read_bytes`]
+    /// only for compressed inputs.
+    pub fn input_bytes(&self) -> u64 {
+        self.input_bytes
+    }
+
+    /// The number of bytes of the line terminators of the entries that are not counted because
+    /// of the [`Report::byte_mode`].
+    pub fn terminator_bytes(&self) -> u64 {
+        self.terminator_bytes
+    }
+
+    /// The number of bytes of the lines that were not counted as entries because they were
+    /// filtered, excluded, or skipped.
+    pub fn other_bytes(&self) -> u64 {
+        self.other_bytes
+    }
+
+    /// Sorts the rows. Ties are broken by the values of the key fields.
+    ///
+    /// If the report has time buckets, the types are sorted by their statistics in all buckets
+    /// and the rows of a type stay together.
+    pub fn sort(&mut self, key: SortKey, reverse: bool) {
+        let time_buckets = self.time_buckets;
+        let mut totals: HashMap<&[Option<Key>], (u64, u64)> = HashMap::new();
+        for (key, data) in &self.rows {
+            let total = totals.entry(type_key(key, time_buckets)).or_default();
+            *total = (total.0 + data.num, total.1 + data.bytes);
+        }
+        // NOTE: The keys are cloned because the rows cannot be borrowed while they are sorted.
+        let totals: HashMap<GroupKey, (u64, u64)> =
+            totals.into_iter().map(|(k, v)| (k.to_vec(), v)).collect();
+        let total = |key: &GroupKey| totals[type_key(key, time_buckets)];
+        // NOTE: The rows are already sorted by name and the sort is stable.
+        match key {
+            SortKey::Name => {}
+            SortKey::Count => self
+                .rows
+                .sort_by_key(|(k, _)| std::cmp::Reverse(total(k).0)),
+            SortKey::Bytes => self
+                .rows
+                .sort_by_key(|(k, _)| std::cmp::Reverse(total(k).1)),
+            SortKey::AvgSize => self.rows.sort_by(|(l, _), (r, _)| {
+                let ((l_num, l_bytes), (r_num, r_bytes)) = (total(l), total(r));
+                // NOTE: The averages are compared exactly by cross-multiplying.
+                let l_avg = l_bytes as u128 * r_num as u128;
+                let r_avg = r_bytes as u128 * l_num as u128;
+                r_avg.cmp(&l_avg)
+            }),
+        }
+        if reverse {
+            self.rows.reverse();
+        }
+    }
+
+    /// Keeps only the first `n` types and combines the remaining types into a single row. If the
+    /// report has time buckets, the remaining types are combined across all buckets.
+    pub fn limit(&mut self, n: usize) {
+        // NOTE: The rows of a type are adjacent because they are sorted by name or by the
+        // statistics of the type.
+        let mut types = 0;
+        let mut last = None;
+        let mut starts = self.rows.iter().map(|(key, _)| {
+            let key = type_key(key, self.time_buckets);
+            let start = last != Some(key);
+            last = Some(key);
+            start
+        });
+        let split = starts.position(|start| {
+            types += start as usize;
+            types > n
+        });
+        let Some(split) = split else {
+            return;
+        };
+        let removed = 1 + starts.filter(|&start| start).count();
+        let mut other = TypeData::default();
+        let rest = self.rows.split_off(split);
+        for (_, data) in &rest {
+            other.merge(data);
+        }
+        self.other = Some((removed, other));
+    }
+
+    /// The rows followed by the row combining the types removed by [`Report::limit`], which has
+    /// no key.
+    fn all_rows(&self) -> impl Iterator<Item = (Option<&GroupKey>, &TypeData)> {
+        let other = self.other.as_ref().map(|(_, data)| (None, data));
+        self.rows
+            .iter()
+            .map(|(key, data)| (Some(key), data))
+            .chain(other)
+    }
+
+    /// Returns the key cells of the row combining the types removed by [`Report::limit`].
+    fn other_cells(&self) -> Vec<String> {
+        let mut cells = vec![String::new(); self.key_fields.len()];
+        if let Some(first) = cells.first_mut() {
+            *first = "<other>".to_string();
+        }
+        cells
+    }
+
+    pub fn write(&self, format: Format, w: &mut impl Write) -> io::Result<()> {
+        match format {
+            Format::Human => self.write_human(w),
+            Format::Json => self.write_json(w),
+            Format::Csv => self.write_separated(w, ',', csv_escape),
+            Format::Tsv => self.write_separated(w, '\t', tsv_escape),
+            Format::Ndjson => self.write_ndjson(w),
+        }
+    }
+
+    fn write_human(&self, w: &mut impl Write) -> io::Result<()> {
+        if self.time_buckets {
+            return self.write_human_matrix(w);
+        }
+        let mut columns: Vec<_> = self
+            .key_fields
+            .iter()
+            .map(|k| (k.clone(), Align::Left))
+            .collect();
+        columns.push(("Number of Objects".to_string(), Align::Right));
+        columns.push(("% of Objects".to_string(), Align::Right));
+        columns.push(("Total Bytes".to_string(), Align::Right));
+        columns.push(("% of Bytes".to_string(), Align::Right));
+        if let Some(field) = &self.distinct_field {
+            columns.push((format!("Distinct {}", field), Align::Right));
+        }
+        columns.push(("Min Size".to_string(), Align::Right));
+        columns.push(("Mean Size".to_string(), Align::Right));
+        columns.push(("Max Size".to_string(), Align::Right));
+        if let Some(field) = &self.value_field {
+            for name in VALUE_COLUMNS {
+                columns.push((format!("{} {}", field, name), Align::Right));
+            }
+        }
+        columns.push(("Size Distribution".to_string(), Align::Left));
+        let mut table = Table::new(columns);
+        // NOTE: All histograms cover the same buckets so that they can be compared.
+        let buckets = self
+            .total
+            .sizes
+            .histogram
+            .iter()
+            .position(|&n| n > 0)
+            .unwrap_or(0)..self.total.sizes.histogram.len();
+        for (key, data) in self.all_rows() {
+            let mut row: Vec<_> = match key {
+                Some(key) => key.iter().map(display_component).collect(),
+                None => self.other_cells(),
+            };
+            row.push(data.num.to_string());
+            row.push(format!("{:.2}%", percent(data.num, self.total.num)));
+            row.push(data.bytes.to_string());
+            row.push(format!("{:.2}%", percent(data.bytes, self.total.bytes)));
+            if self.distinct_field.is_some() {
+                row.push(data.distinct.count().to_string());
+            }
+            let sizes = &data.sizes;
+            let present = |v: u64| (sizes.count() > 0).then_some(v as f64);
+            let size_cells = [present(sizes.min), sizes.mean(), present(sizes.max)];
+            row.extend(size_cells.iter().map(format_human));
+            if self.value_field.is_some() {
+                row.extend(value_cells(&data.values).iter().map(format_human));
+            }
+            row.push(sparkline(&sizes.histogram, buckets.clone()));
+            table.push(row);
+        }
+        table.write(w)?;
+        if !buckets.is_empty() {
+            let (min, _) = size_bucket_range(buckets.start);
+            let (_, max) = size_bucket_range(buckets.end - 1);
+            writeln!(
+                w,
+                "\nSize distribution: one column per power of two from {} to {} bytes",
+                min, max
+            )?;
+        }
+        if self.lines > 0 {
+            writeln!(w, "{}", self.byte_summary())?;
+        }
+        Ok(())
+    }
+
+    /// Writes one table of the numbers of objects and one table of the bytes with one row per
+    /// type and one column per time bucket.
+    fn write_human_matrix(&self, w: &mut impl Write) -> io::Result<()> {
+        let matrix = self.matrix();
+        for (i, (title, bytes)) in [("Number of Objects", false), ("Total Bytes", true)]
+            .into_iter()
+            .enumerate()
+        {
+            let value = |&(objects, b): &(u64, u64)| if bytes { b } else { objects };
+            if i > 0 {
+                writeln!(w)?;
+            }
+            writeln!(w, "{}", title)?;
+            let mut columns: Vec<_> = matrix
+                .key_fields
+                .iter()
+                .map(|k| (k.clone(), Align::Left))
+                .collect();
+            columns.extend(
+                matrix
+                    .buckets
+                    .iter()
+                    .map(|b| (bucket_label(b), Align::Right)),
+            );
+            columns.push(("Total".to_string(), Align::Right));
+            let mut table = Table::new(columns);
+            for row in &matrix.rows {
+                let mut cells = match row.key {
+                    Some(key) => key.iter().map(display_component).collect(),
+                    None => matrix.other_cells.clone(),
+                };
+                match row.key {
+                    Some(_) => cells.extend(row.buckets.iter().map(|c| value(c).to_string())),
+                    None => cells.extend(matrix.buckets.iter().map(|_| "-".to_string())),
+                }
+                cells.push(value(&row.total).to_string());
+                table.push(cells);
+            }
+            table.write(w)?;
+        }
+        if self.lines > 0 {
+            writeln!(w, "\n{}", self.byte_summary())?;
+        }
+        Ok(())
+    }
+
+    /// Breaks down the rows by time bucket. Must only be called if the report has time buckets.
+    fn matrix(&self) -> Matrix<'_> {
+        let buckets: BTreeSet<_> = self.rows.iter().filter_map(|(key, _)| key.last()).collect();
+        let buckets: Vec<_> = buckets.into_iter().collect();
+        let mut rows: Vec<MatrixRow> = vec![];
+        let mut index = HashMap::new();
+        for (key, data) in &self.rows {
+            let bucket = key.last().expect("the time bucket is a key field");
+            let key = type_key(key, true);
+            let i = *index.entry(key).or_insert_with(|| {
+                rows.push(MatrixRow {
+                    key: Some(key),
+                    buckets: vec![(0, 0); buckets.len()],
+                    total: (0, 0),
+                });
+                rows.len() - 1
+            });
+            let row = &mut rows[i];
+            let cell = (data.num, data.bytes);
+            let bucket = buckets.binary_search(&bucket).unwrap();
+            row.buckets[bucket] = cell;
+            row.total = (row.total.0 + cell.0, row.total.1 + cell.1);
+        }
+        if let Some((_, data)) = &self.other {
+            rows.push(MatrixRow {
+                key: None,
+                buckets: vec![],
+                total: (data.num, data.bytes),
+            });
+        }
+        let mut other_cells = self.other_cells();
+        other_cells.pop();
+        Matrix {
+            key_fields: &self.key_fields[..self.key_fields.len() - 1],
+            buckets,
+            rows,
+            other_cells,
+        }
+    }
+
+    /// Reconciles the total of the bytes column with the size of the inputs.
+    fn byte_summary(&self) -> String {
+        // NOTE: If the compressed size is counted, the line terminators are counted as well and
+        // the bytes of the other lines are scaled like the sizes of the entries.
+        match self.count_compressed {
+            true => {
+                let mut summary = format!(
+                    "Bytes: {} in entries + {} in other lines = {} bytes of input",
+                    self.total.bytes, self.other_bytes, self.input_bytes
+                );
+                if self.read_bytes != self.input_bytes {
+                    summary.push_str(&format!(" ({} bytes decompressed)", self.read_bytes));
+                }
+                summary
+            }
+            false => {
+                let mut summary = format!(
+                    "Bytes: {} in entries + {} in uncounted line terminators + {} in other lines \
+                     = {} bytes read",
+                    self.total.bytes, self.terminator_bytes, self.other_bytes, self.read_bytes
+                );
+                if self.read_bytes != self.input_bytes {
+                    summary.push_str(&format!(" from {} bytes of input", self.input_bytes));
+                }
+                summary
+            }
+        }
+    }
+
+    fn json_values(&self, values: &ValueStats) -> Option<Map<String, Value>> {
+        self.value_field.as_ref()?;
+        let cells = value_cells(values);
+        let mut map: Map<_, _> = VALUE_COLUMNS
+            .iter()
+            .zip(cells)
+            .map(|(name, cell)| (name.to_string(), cell.map(Value::from).unwrap_or_default()))
+            .collect();
+        map.insert("count".to_string(), Value::from(values.count));
+        Some(map)
+    }
+
+    fn json_data(&self, data: &TypeData) -> JsonData {
+        JsonData {
+            objects: data.num,
+            objects_percent: percent(data.num, self.total.num),
+            bytes: data.bytes,
+            bytes_percent: percent(data.bytes, self.total.bytes),
+            distinct: self.distinct_field.as_ref().map(|_| data.distinct.count()),
+            sizes: json_sizes(&data.sizes),
+            values: self.json_values(&data.values),
+        }
+    }
+
+    fn json_type(&self, key: &GroupKey, data: &TypeData) -> JsonType {
+        JsonType {
+            key: key_object(&self.key_fields, key),
+            data: self.json_data(data),
+        }
+    }
+
+    fn json_other(&self) -> Option<JsonOther> {
+        let (types, data) = self.other.as_ref()?;
+        Some(JsonOther {
+            types: *types,
+            data: self.json_data(data),
+        })
+    }
+
+    fn json_totals(&self) -> JsonTotals {
+        JsonTotals {
+            objects: self.total.num,
+            bytes: self.total.bytes,
+            distinct: self
+                .distinct_field
+                .as_ref()
+                .map(|_| self.total.distinct.count()),
+            sizes: json_sizes(&self.total.sizes),
+            values: self.json_values(&self.total.values),
+            lines: self.lines,
+            excluded_lines: self.excluded,
+            filtered_lines: self.filtered,
+            skipped_lines: self.errors.total(),
+            invalid_utf8_lines: self.invalid_utf8,
+            byte_mode: self.byte_mode,
+            read_bytes: self.read_bytes,
+            input_bytes: self.input_bytes,
+            terminator_bytes: self.terminator_bytes,
+            other_bytes: self.other_bytes,
+        }
+    }
+
+    fn write_json(&self, w: &mut impl Write) -> io::Result<()> {
+        let doc = JsonReport {
+            schema_version: SCHEMA_VERSION,
+            key_fields: &self.key_fields,
+            value_field: self.value_field.as_deref(),
+            distinct_field: self.distinct_field.as_deref(),
+            types: self
+                .rows
+                .iter()
+                .map(|(key, data)| self.json_type(key, data))
+                .collect(),
+            other: self.json_other(),
+            totals: self.json_totals(),
+        };
+        serde_json::to_writer_pretty(&mut *w, &doc)?;
+        writeln!(w)
+    }
+
+    fn write_ndjson(&self, w: &mut impl Write) -> io::Result<()> {
+        for (key, data) in &self.rows {
+            write_ndjson_record(w, "type", self.json_type(key, data))?;
+        }
+        if let Some(other) = self.json_other() {
+            write_ndjson_record(w, "other", other)?;
+        }
+        write_ndjson_record(w, "totals", self.json_totals())
+    }
+
+    /// Writes one row per type. Key fields are written in their json form and absent key fields
+    /// are written as empty cells. The row combining the types removed by [`Report::limit`]
+    /// contains `<other>` in the first key field.
+    ///
+    /// If the report has time buckets, the rows contain the numbers of objects and bytes in total
+    /// and in each bucket instead of the other statistics. The cells of the row combining the
+    /// types removed by [`Report::limit`] are empty for the buckets.
+    fn write_separated(
+        &self,
+        w: &mut impl Write,
+        separator: char,
+        escape: fn(&str) -> String,
+    ) -> io::Result<()> {
+        let mut write_row = |cells: Vec<String>| {
+            let cells: Vec<_> = cells.iter().map(|c| escape(c)).collect();
+            writeln!(w, "{}", cells.join(&separator.to_string()))
+        };
+        if self.time_buckets {
+            let matrix = self.matrix();
+            let mut header = vec!["schema_version".to_string()];
+            header.extend(matrix.key_fields.iter().cloned());
+            header.push("objects".to_string());
+            header.push("bytes".to_string());
+            for bucket in &matrix.buckets {
+                header.push(format!("objects_{}", bucket_label(bucket)));
+                header.push(format!("bytes_{}", bucket_label(bucket)));
+            }
+            write_row(header)?;
+            for row in &matrix.rows {
+                let mut cells = vec![SCHEMA_VERSION.to_string()];
+                match row.key {
+                    Some(key) => cells.extend(key.iter().map(separated_component)),
+                    None => cells.extend(matrix.other_cells.iter().cloned()),
+                }
+                cells.push(row.total.0.to_string());
+                cells.push(row.total.1.to_string());
+                match row.key {
+                    Some(_) => {
+                        for (objects, bytes) in &row.buckets {
+                            cells.push(objects.to_string());
+                            cells.push(bytes.to_string());
+                        }
+                    }
+                    None => cells.extend(vec![String::new(); 2 * matrix.buckets.len()]),
+                }
+                write_row(cells)?;
+            }
+            return Ok(());
+        }
+        // NOTE: New columns are appended so that consumers that address columns by position keep
+        // working without a new schema version. For the same reason, the optional columns are
+        // always written and left empty if they were not requested.
+        let mut header = vec!["schema_version".to_string()];
+        header.extend(self.key_fields.iter().cloned());
+        for column in [
+            "objects",
+            "bytes",
+            "min_size",
+            "mean_size",
+            "max_size",
+            "size_histogram",
+            "objects_percent",
+            "bytes_percent",
+        ] {
+            header.push(column.to_string());
+        }
+        header.extend(VALUE_COLUMNS.iter().map(|c| format!("value_{}", c)));
+        header.push("distinct".to_string());
+        write_row(header)?;
+        for (key, data) in self.all_rows() {
+            let mut row = vec![SCHEMA_VERSION.to_string()];
+            match key {
+                Some(key) => row.extend(key.iter().map(separated_component)),
+                None => row.extend(self.other_cells()),
+            }
+            row.push(data.num.to_string());
+            row.push(data.bytes.to_string());
+            let sizes = json_sizes(&data.sizes);
+            let optional = |v: Option<String>| v.unwrap_or_default();
+            row.push(optional(sizes.min.map(|v| v.to_string())));
+            row.push(optional(sizes.mean.map(|v| v.to_string())));
+            row.push(optional(sizes.max.map(|v| v.to_string())));
+            row.push(serde_json::to_string(&sizes.histogram)?);
+            row.push(percent(data.num, self.total.num).to_string());
+            row.push(percent(data.bytes, self.total.bytes).to_string());
+            let values = match self.value_field {
+                Some(_) => value_cells(&data.values),
+                None => [None; VALUE_COLUMNS.len()],
+            };
+            row.extend(values.iter().map(|c| optional(c.map(|c| c.to_string()))));
+            let distinct = self.distinct_field.as_ref().map(|_| data.distinct.count());
+            row.push(optional(distinct.map(|n| n.to_string())));
+            write_row(row)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the fields of each type as collected by the `schema` subcommand.
+    pub fn write_schema(&self, format: Format, w: &mut impl Write) -> io::Result<()> {
+        match format {
+            Format::Human => self.write_schema_human(w),
+            Format::Json => {
+                let doc = JsonSchemaReport {
+                    schema_version: SCHEMA_VERSION,
+                    key_fields: &self.key_fields,
+                    types: self
+                        .rows
+                        .iter()
+                        .map(|(key, data)| self.json_type_fields(key, data))
+                        .collect(),
+                    other: self.json_other_fields(),
+                };
+                serde_json::to_writer_pretty(&mut *w, &doc)?;
+                writeln!(w)
+            }
+            Format::Csv => self.write_schema_separated(w, ',', csv_escape),
+            Format::Tsv => self.write_schema_separated(w, '\t', tsv_escape),
+            Format::Ndjson => {
+                for (key, data) in &self.rows {
+                    write_ndjson_record(w, "type", self.json_type_fields(key, data))?;
+                }
+                if let Some(other) = self.json_other_fields() {
+                    write_ndjson_record(w, "other", other)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Writes a JSON Schema (draft 2020-12) that accepts the entries of all types.
+    pub fn write_json_schema(&self, w: &mut impl Write) -> io::Result<()> {
+        let mut doc = Map::new();
+        doc.insert("$schema".to_string(), Value::from(JSON_SCHEMA_DIALECT));
+        let types: Vec<_> = self
+            .all_rows()
+            .map(|(key, data)| {
+                let mut schema = data.schema.json_schema();
+                schema.insert("title".to_string(), Value::from(self.title(key)));
+                Value::Object(schema)
+            })
+            .collect();
+        // NOTE: `anyOf` must not be empty. Without any types, no entry is valid.
+        match types.is_empty() {
+            true => doc.insert("not".to_string(), Value::Object(Map::new())),
+            false => doc.insert("anyOf".to_string(), Value::Array(types)),
+        };
+        serde_json::to_writer_pretty(&mut *w, &doc)?;
+        writeln!(w)
+    }
+
+    /// Describes a row by its key (see [`key_title`]).
+    fn title(&self, key: Option<&GroupKey>) -> String {
+        match key {
+            Some(key) => key_title(&self.key_fields, key),
+            None => {
+                let types = self.other.as_ref().map(|(n, _)| *n).unwrap_or_default();
+                format!("<other>: {} types", types)
+            }
+        }
+    }
+
+    fn write_schema_human(&self, w: &mut impl Write) -> io::Result<()> {
+        for (i, (key, data)) in self.all_rows().enumerate() {
+            if i > 0 {
+                writeln!(w)?;
+            }
+            writeln!(w, "{} ({} objects)", self.title(key), data.num)?;
+            let mut table = Table::new(vec![
+                ("Field".to_string(), Align::Left),
+                ("Kinds".to_string(), Align::Left),
+                ("Presence".to_string(), Align::Right),
+                ("Examples".to_string(), Align::Left),
+            ]);
+            for field in data.schema.fields() {
+                let kinds = match &field.kinds[..] {
+                    [(kind, _)] => kind.name().to_string(),
+                    kinds => kinds
+                        .iter()
+                        .map(|(kind, n)| {
+                            format!("{} {:.2}%", kind.name(), percent(*n, field.present))
+                        })
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                };
+                let examples: Vec<_> = field
+                    .examples
+                    .iter()
+                    .map(|v| truncate(&v.to_string(), MAX_EXAMPLE_WIDTH))
+                    .collect();
+                table.push(vec![
+                    field.path,
+                    kinds,
+                    format!("{:.2}%", percent(field.present, data.schema.entries())),
+                    examples.join(", "),
+                ]);
+            }
+            table.write(w)?;
+        }
+        Ok(())
+    }
+
+    fn json_fields(&self, data: &TypeData) -> JsonFields {
+        let fields = data
+            .schema
+            .fields()
+            .into_iter()
+            .map(|field| JsonField {
+                objects_percent: percent(field.present, data.schema.entries()),
+                objects: field.present,
+                kinds: field
+                    .kinds
+                    .iter()
+                    .map(|(kind, n)| (kind.name().to_string(), Value::from(*n)))
+                    .collect(),
+                examples: field.examples.to_vec(),
+                path: field.path,
+            })
+            .collect();
+        JsonFields {
+            objects: data.num,
+            fields,
+        }
+    }
+
+    fn json_type_fields(&self, key: &GroupKey, data: &TypeData) -> JsonTypeFields {
+        JsonTypeFields {
+            key: key_object(&self.key_fields, key),
+            fields: self.json_fields(data),
+        }
+    }
+
+    fn json_other_fields(&self) -> Option<JsonOtherFields> {
+        let (types, data) = self.other.as_ref()?;
+        Some(JsonOtherFields {
+            types: *types,
+            fields: self.json_fields(data),
+        })
+    }
+
+    /// Writes one row per field and type. The columns of the key fields are written as in
+    /// [`Report::write_separated`].
+    fn write_schema_separated(
+        &self,
+        w: &mut impl Write,
+        separator: char,
+        escape: fn(&str) -> String,
+    ) -> io::Result<()> {
+        let mut write_row = |cells: Vec<String>| {
+            let cells: Vec<_> = cells.iter().map(|c| escape(c)).collect();
+            writeln!(w, "{}", cells.join(&separator.to_string()))
+        };
+        let mut header = vec!["schema_version".to_string()];
+        header.extend(self.key_fields.iter().cloned());
+        for column in ["path", "objects", "objects_percent", "kinds", "examples"] {
+            header.push(column.to_string());
+        }
+        write_row(header)?;
+        for (key, data) in self.all_rows() {
+            let key_cells: Vec<_> = match key {
+                Some(key) => key
+                    .iter()
+                    .map(|v| v.as_ref().map(|v| v.to_string()).unwrap_or_default())
+                    .collect(),
+                None => self.other_cells(),
+            };
+            for field in self.json_fields(data).fields {
+                let mut row = vec![SCHEMA_VERSION.to_string()];
+                row.extend(key_cells.iter().cloned());
+                row.push(field.path);
+                row.push(field.objects.to_string());
+                row.push(field.objects_percent.to_string());
+                row.push(serde_json::to_string(&field.kinds)?);
+                row.push(serde_json::to_string(&field.examples)?);
+                write_row(row)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The value of the `$schema` keyword of the documents written by [`Report::write_json_schema`].
+const JSON_SCHEMA_DIALECT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+/// The maximum width of an example value in the human format.
+const MAX_EXAMPLE_WIDTH: usize = 40;
+
+/// Shortens a string to at most `max` characters by replacing its end with `…`.
+fn truncate(s: &str, max: usize) -> String {
+    if s.chars().count() <= max {
+        return s.to_string();
+    }
+    let mut res: String = s.chars().take(max - 1).collect();
+    res.push('…');
+    res
+}
+
+/// The statistics reported for the field selected with `--value`.
+const VALUE_COLUMNS: [&str; 9] = [
+    "count", "sum", "min", "max", "mean", "stddev", "p50", "p90", "p99",
+];
+
+/// Returns the statistics in the order of `VALUE_COLUMNS`. Statistics of empty sets are `None`.
+fn value_cells(values: &ValueStats) -> [Option<f64>; VALUE_COLUMNS.len()] {
+    let present = |v: f64| (values.count > 0).then_some(v);
+    let [p50, p90, p99] = values.quantiles(QUANTILES);
+    [
+        Some(values.count as f64),
+        Some(values.sum),
+        present(values.min),
+        present(values.max),
+        values.mean(),
+        values.stddev(),
+        p50,
+        p90,
+        p99,
+    ]
+}
+
+/// Formats a statistic for the human format. Integers are printed without a fractional part and
+/// other numbers with 3 decimal places.
+fn format_human(value: &Option<f64>) -> String {
+    match value {
+        None => "-".to_string(),
+        Some(v) if v.fract() == 0.0 && v.abs() < 1e15 => format!("{:.0}", v),
+        Some(v) => format!("{:.3}", v),
+    }
+}
+
+/// Returns the key of a row without the start of the time bucket if the report has time buckets.
+fn type_key(key: &GroupKey, time_buckets: bool) -> &[Option<Key>] {
+    &key[..key.len() - time_buckets as usize]
+}
+
+/// Formats one component of a [`GroupKey`] for the separated formats. Absent components are
+/// empty.
+fn separated_component(component: &Option<Key>) -> String {
+    component
+        .as_ref()
+        .map(|v| v.to_string())
+        .unwrap_or_default()
+}
+
+/// Formats the start of a time bucket without the quotes of its json form.
+fn bucket_label(bucket: &Option<Key>) -> String {
+    match bucket {
+        Some(Key(Value::String(s))) => s.clone(),
+        bucket => display_component(bucket),
+    }
+}
+
+/// Returns the key as a json object mapping the key fields to their values. Absent fields are
+/// omitted.
+pub fn key_object(key_fields: &[String], key: &GroupKey) -> Value {
+    let mut map = Map::new();
+    for (field, value) in key_fields.iter().zip(key) {
+        if let Some(value) = value {
+            map.insert(field.clone(), value.0.clone());
+        }
+    }
+    Value::Object(map)
+}
+
+/// Describes a type by its key, e.g. `type: "a", level: "info"`.
+pub fn key_title(key_fields: &[String], key: &GroupKey) -> String {
+    let parts: Vec<_> = key_fields
+        .iter()
+        .zip(key)
+        .map(|(field, value)| format!("{}: {}", field, display_component(value)))
+        .collect();
+    parts.join(", ")
+}
+
+/// Returns the change from `old` to `new` as a percentage of `old` or `None` if `old` is 0.
+pub fn percent_change(old: u64, new: u64) -> Option<f64> {
+    (old > 0).then(|| 100.0 * (new as f64 - old as f64) / old as f64)
+}
+
+/// Returns `part` as a percentage of `total`.
+fn percent(part: u64, total: u64) -> f64 {
+    match total {
+        0 => 0.0,
+        _ => 100.0 * part as f64 / total as f64,
+    }
+}
+
+/// Characters representing increasing fractions of the largest bucket of a histogram.
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Renders the buckets of a histogram as one character each. Empty buckets are rendered as
+/// spaces and all other buckets relative to the largest bucket.
+fn sparkline(histogram: &[u64], buckets: Range<usize>) -> String {
+    let max = histogram.iter().copied().max().unwrap_or_default();
+    buckets
+        .map(|i| match histogram.get(i).copied().unwrap_or_default() {
+            0 => ' ',
+            n => SPARKS[((n as u128 * SPARKS.len() as u128 - 1) / max as u128) as usize],
+        })
+        .collect()
+}
+
+fn json_sizes(sizes: &SizeStats) -> JsonSizes {
+    let present = |v: u64| (sizes.count() > 0).then_some(v);
+    JsonSizes {
+        min: present(sizes.min),
+        mean: sizes.mean(),
+        max: present(sizes.max),
+        histogram: sizes
+            .histogram
+            .iter()
+            .enumerate()
+            .filter(|(_, &n)| n > 0)
+            .map(|(i, &count)| {
+                let (min, max) = size_bucket_range(i);
+                JsonSizeBucket { min, max, count }
+            })
+            .collect(),
+    }
+}
+
+/// The rows of a report with time buckets broken down by bucket.
+struct Matrix<'a> {
+    /// The key fields without the time bucket.
+    key_fields: &'a [String],
+    /// The starts of the time buckets in chronological order.
+    buckets: Vec<&'a Option<Key>>,
+    rows: Vec<MatrixRow<'a>>,
+    /// The key cells of the row combining the types removed by [`Report::limit`].
+    other_cells: Vec<String>,
+}
+
+/// A type of a [`Matrix`].
+struct MatrixRow<'a> {
+    /// The key without the time bucket. `None` for the row combining the types removed by
+    /// [`Report::limit`].
+    key: Option<&'a [Option<Key>]>,
+    /// The numbers of objects and bytes in each bucket. Empty if `key` is `None`.
+    buckets: Vec<(u64, u64)>,
+    /// The numbers of objects and bytes in all buckets.
+    total: (u64, u64),
+}
+
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    schema_version: u32,
+    key_fields: &'a [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    value_field: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    distinct_field: Option<&'a str>,
+    types: Vec<JsonType>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    other: Option<JsonOther>,
+    totals: JsonTotals,
+}
+
+#[derive(Serialize)]
+struct JsonType {
+    key: Value,
+    #[serde(flatten)]
+    data: JsonData,
+}
+
+/// The types that were omitted because of `--top`.
+#[derive(Serialize)]
+struct JsonOther {
+    /// The number of omitted types.
+    types: usize,
+    #[serde(flatten)]
+    data: JsonData,
+}
+
+#[derive(Serialize)]
+struct JsonData {
+    objects: u64,
+    /// The percentage of all objects.
+    objects_percent: f64,
+    bytes: u64,
+    /// The percentage of all bytes.
+    bytes_percent: f64,
+    /// The (estimated) number of distinct values of the distinct field.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    distinct: Option<u64>,
+    sizes: JsonSizes,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    values: Option<Map<String, Value>>,
+}
+
+/// The distribution of the sizes of the entries. Statistics of empty sets are null.
+#[derive(Serialize)]
+struct JsonSizes {
+    min: Option<u64>,
+    mean: Option<f64>,
+    max: Option<u64>,
+    /// The non-empty buckets of the log-scale histogram.
+    histogram: Vec<JsonSizeBucket>,
+}
+
+#[derive(Serialize)]
+struct JsonSizeBucket {
+    /// The smallest size in the bucket.
+    min: u64,
+    /// The largest size in the bucket.
+    max: u64,
+    count: u64,
+}
+
+#[derive(Serialize)]
+struct JsonTotals {
+    objects: u64,
+    bytes: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    distinct: Option<u64>,
+    sizes: JsonSizes,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    values: Option<Map<String, Value>>,
+    /// The number of lines that were read, including the ones that were skipped.
+    lines: u64,
+    /// The number of lines whose timestamps are outside of the time range.
+    excluded_lines: u64,
+    /// The number of lines that do not match the filters.
+    filtered_lines: u64,
+    skipped_lines: u64,
+    /// The number of lines that are not valid UTF-8, including skipped lines.
+    invalid_utf8_lines: u64,
+    byte_mode: ByteMode,
+    /// The number of decompressed bytes that were read.
+    read_bytes: u64,
+    /// The size of the inputs as stored.
+    input_bytes: u64,
+    /// The bytes of the line terminators of the entries that are not counted.
+    terminator_bytes: u64,
+    /// The bytes of the lines that were filtered, excluded, or skipped.
+    other_bytes: u64,
+}
+
+#[derive(Serialize)]
+struct JsonSchemaReport<'a> {
+    schema_version: u32,
+    key_fields: &'a [String],
+    types: Vec<JsonTypeFields>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    other: Option<JsonOtherFields>,
+}
+
+#[derive(Serialize)]
+struct JsonTypeFields {
+    key: Value,
+    #[serde(flatten)]
+    fields: JsonFields,
+}
+
+/// The fields of the types that were omitted because of `--top`.
+#[derive(Serialize)]
+struct JsonOtherFields {
+    /// The number of omitted types.
+    types: usize,
+    #[serde(flatten)]
+    fields: JsonFields,
+}
+
+#[derive(Serialize)]
+struct JsonFields {
+    objects: u64,
+    fields: Vec<JsonField>,
+}
+
+#[derive(Serialize)]
+struct JsonField {
+    /// A JSON Pointer in which the elements of arrays are represented by the segment `*`.
+    path: String,
+    /// The number of objects containing the field.
+    objects: u64,
+    /// The percentage of the objects of the type containing the field.
+    objects_percent: f64,
+    /// The number of objects in which the field has a value of each kind.
+    kinds: Map<String, Value>,
+    /// Distinct values of the field that are neither arrays nor objects.
+    examples: Vec<Value>,
+}
+
+#[derive(Serialize)]
+struct NdjsonRecord<T> {
+    schema_version: u32,
+    record: &'static str,
+    #[serde(flatten)]
+    fields: T,
+}
+
+pub fn write_ndjson_record(
+    w: &mut impl Write,
+    record: &'static str,
+    fields: impl Serialize,
+) -> io::Result<()> {
+    let record = NdjsonRecord {
+        schema_version: SCHEMA_VERSION,
+        record,
+        fields,
+    };
+    serde_json::to_writer(&mut *w, &record)?;
+    writeln!(w)
+}
+
+/// Quotes a field as described in RFC 4180 if necessary.
+pub fn csv_escape(s: &str) -> String {
+    if s.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+/// Escapes characters that cannot appear in a field of the tab-separated format.
+pub fn tsv_escape(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => res.push_str("\\\\"),
+            '\t' => res.push_str("\\t"),
+            '\n' => res.push_str("\\n"),
+            '\r' => res.push_str("\\r"),
+            _ => res.push(c),
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::ErrorStats;
+    use crate::key::Key;
+    use serde_json::json;
+
+    const HEADER: &str = "schema_version,type,level,objects,bytes,min_size,mean_size,max_size,\
+        size_histogram,objects_percent,bytes_percent,value_count,value_sum,value_min,value_max,\
+        value_mean,value_stddev,value_p50,value_p90,value_p99,distinct";
+
+    fn separated(value: bool, distinct: bool, format: Format) -> String {
+        let mut stats = Stats::new(ErrorStats::new(0));
+        let mut data = TypeData {
+            num: 1,
+            bytes: 10,
+            ..Default::default()
+        };
+        data.sizes.add(10);
+        data.values.add(1.5, false);
+        data.distinct.add(0, false);
+        stats.types.insert(vec![Some(Key(json!("a"))), None], data);
+        let report = Report::new(
+            vec!["type".to_string(), "level".to_string()],
+            false,
+            value.then(|| "value".to_string()),
+            distinct.then(|| "user".to_string()),
+            ByteMode::Content,
+            false,
+            &stats,
+        );
+        let mut out = vec![];
+        report.write(format, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn separated_columns() {
+        let out = separated(true, true, Format::Csv);
+        let mut lines = out.lines();
+        assert_eq!(lines.next().unwrap(), HEADER);
+        assert_eq!(
+            lines.next().unwrap(),
+            r#"1,"""a""",,1,10,10,10,10,"[{""min"":8,""max"":15,""count"":1}]",100,100,1,1.5,1.5,1.5,1.5,0,1.5,1.5,1.5,1"#
+        );
+        assert_eq!(lines.next(), None);
+    }
+
+    #[test]
+    fn optional_columns_keep_their_position() {
+        for (value, distinct) in [(false, false), (true, false), (false, true), (true, true)] {
+            let csv = separated(value, distinct, Format::Csv);
+            let tsv = separated(value, distinct, Format::Tsv);
+            assert_eq!(csv.lines().next().unwrap(), HEADER);
+            assert_eq!(tsv.lines().next().unwrap(), HEADER.replace(',', "\t"));
+            let row: Vec<_> = tsv.lines().nth(1).unwrap().split('\t').collect();
+            assert_eq!(row.len(), HEADER.split(',').count());
+            let optional = |cells: &[&str], present| match present {
+                true => assert!(cells.iter().all(|c| !c.is_empty())),
+                false => assert!(cells.iter().all(|c| c.is_empty())),
+            };
+            optional(&row[11..20], value);
+            optional(&row[20..], distinct);
+        }
+    }
+
+    /// Creates a report with time buckets of the rows `(type, bucket, objects, bytes)`.
+    fn bucketed(rows: &[(&str, &str, u64, u64)]) -> Report {
+        let mut stats = Stats::new(ErrorStats::new(0));
+        for &(ty, bucket, num, bytes) in rows {
+            let data = TypeData {
+                num,
+                bytes,
+                ..Default::default()
+            };
+            let key = vec![Some(Key(json!(ty))), Some(Key(json!(bucket)))];
+            stats.types.insert(key, data);
+        }
+        let key_fields = vec!["type".to_string(), "time".to_string()];
+        Report::new(
+            key_fields,
+            true,
+            None,
+            None,
+            ByteMode::Content,
+            false,
+            &stats,
+        )
+    }
+
+    fn output(report: &Report, format: Format) -> String {
+        let mut out = vec![];
+        report.write(format, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn time_bucket_matrix() {
+        let mut report = bucketed(&[
+            ("a", "2022-05-01T12:00:00Z", 1, 10),
+            ("a", "2022-05-01T13:00:00Z", 2, 30),
+            ("b", "2022-05-01T13:00:00Z", 1, 5),
+            ("c", "2022-05-01T12:00:00Z", 1, 100),
+            ("d", "2022-05-01T14:00:00Z", 2, 1),
+        ]);
+        // NOTE: The types are sorted and limited by their statistics in all buckets.
+        report.sort(SortKey::Count, false);
+        report.limit(2);
+        assert_eq!(report.other().unwrap().0, 2);
+        let expected = "\
+schema_version,type,objects,bytes,objects_2022-05-01T12:00:00Z,bytes_2022-05-01T12:00:00Z,\
+objects_2022-05-01T13:00:00Z,bytes_2022-05-01T13:00:00Z,objects_2022-05-01T14:00:00Z,\
+bytes_2022-05-01T14:00:00Z
+1,\"\"\"a\"\"\",3,40,1,10,2,30,0,0
+1,\"\"\"d\"\"\",2,1,0,0,0,0,2,1
+1,<other>,2,105,,,,,,
+";
+        assert_eq!(output(&report, Format::Csv), expected);
+        let expected = "\
+Number of Objects
+type     2022-05-01T12:00:00Z  2022-05-01T13:00:00Z  2022-05-01T14:00:00Z  Total
+\"a\"                         1                     2                     0      3
+\"d\"                         0                     0                     2      2
+<other>                     -                     -                     -      2
+
+Total Bytes
+type     2022-05-01T12:00:00Z  2022-05-01T13:00:00Z  2022-05-01T14:00:00Z  Total
+\"a\"                        10                    30                     0     40
+\"d\"                         0                     0                     1      1
+<other>                     -                     -                     -    105
+";
+        assert_eq!(output(&report, Format::Human), expected);
+    }
+}
@@ -0,0 +1,886 @@
+use crate::path::FieldPath;
+use serde::de::{DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::Deserialize;
+use serde_json::{Map, Number, Value};
+use std::fmt;
+use std::ops::Range;
+
+/// A fast path for extracting fields from a json document.
+///
+/// Instead of building a `serde_json::Value`, the document is validated and skipped over and only
+/// the raw bytes of the requested fields are returned. This avoids almost all allocations.
+///
+/// The scanner gives up on documents it cannot handle without allocating, in which case the
+/// caller should fall back to `serde_json`. This happens if
+///
+/// - the document is not valid json (so that serde_json can produce a proper error message),
+/// - an object key along one of the paths contains an escape sequence,
+/// - a string contains an escaped surrogate (serde_json rejects unpaired surrogates),
+/// - the document is nested too deeply (serde_json has a recursion limit).
+///
+/// The result is always the same as if the document had been parsed with serde_json and the
+/// paths had been looked up with [`FieldPath::lookup`].
+pub struct Scanner {
+    root: Node,
+    num_paths: usize,
+}
+
+#[derive(Default)]
+struct Node {
+    /// The children of this node and the path segments leading to them.
+    children: Vec<(Segment, Node)>,
+    /// The indices of the paths ending at this node.
+    terminals: Vec<usize>,
+    /// The indices of the paths ending at this node or one of its descendants.
+    subtree: Vec<usize>,
+}
+
+struct Segment {
+    key: Vec<u8>,
+    /// The segment interpreted as an array index.
+    index: Option<usize>,
+}
+
+/// The reason why a document could not be scanned.
+#[derive(Debug)]
+pub struct Unsupported;
+
+/// serde_json fails with a recursion limit error at a depth of 128. We stop a bit earlier.
+const MAX_DEPTH: usize = 120;
+
+impl Scanner {
+    pub fn new(paths: &[FieldPath]) -> Self {
+        let mut root = Node::default();
+        for (idx, path) in paths.iter().enumerate() {
+            let mut node = &mut root;
+            node.subtree.push(idx);
+            for segment in path.segments() {
+                let pos = node
+                    .children
+                    .iter()
+                    .position(|(s, _)| s.key == segment.as_bytes());
+                let pos = match pos {
+                    Some(pos) => pos,
+                    None => {
+                        let segment = Segment {
+                            key: segment.as_bytes().to_vec(),
+                            index: crate::path::parse_index(segment),
+                        };
+                        node.children.push((segment, Node::default()));
+                        node.children.len() - 1
+                    }
+                };
+                node = &mut node.children[pos].1;
+                node.subtree.push(idx);
+            }
+            node.terminals.push(idx);
+        }
+        Self {
+            root,
+            num_paths: paths.len(),
+        }
+    }
+
+    /// Scans the document and stores the location of the raw value of the i-th path in `out[i]`.
+    /// Paths that do not exist in the document are set to `None`.
+    pub fn scan(&self, doc: &[u8], out: &mut Vec<Option<Range<usize>>>) -> Result<(), Unsupported> {
+        out.clear();
+        out.resize(self.num_paths, None);
+        let mut parser = Parser { doc, pos: 0, out };
+        parser.value(Some(&self.root), 0)?;
+        parser.skip_whitespace();
+        if parser.pos != doc.len() {
+            return Err(Unsupported);
+        }
+        Ok(())
+    }
+}
+
+impl Scanner {
+    /// Parses a document with serde_json but only builds the values at the paths. Looking up the
+    /// paths in the result gives the same values as in the fully parsed document.
+    ///
+    /// This is the slow path for documents that cannot be scanned. The document is validated
+    /// exactly like `serde_json::from_str` so that the same documents are rejected.
+    pub fn parse(&self, doc: &str) -> serde_json::Result<Value> {
+        let mut de = serde_json::Deserializer::from_str(doc);
+        let value = Pruned(&self.root).deserialize(&mut de)?;
+        de.end()?;
+        Ok(value)
+    }
+}
+
+impl Node {
+    fn object_child(&self, key: &[u8]) -> Option<&Node> {
+        self.children
+            .iter()
+            .find(|(s, _)| s.key == key)
+            .map(|(_, n)| n)
+    }
+
+    fn array_child(&self, index: usize) -> Option<&Node> {
+        self.children
+            .iter()
+            .find(|(s, _)| s.index == Some(index))
+            .map(|(_, n)| n)
+    }
+}
+
+struct Parser<'a, 'b> {
+    doc: &'a [u8],
+    pos: usize,
+    out: &'b mut Vec<Option<Range<usize>>>,
+}
+
+impl<'a, 'b> Parser<'a, 'b> {
+    fn peek(&self) -> Option<u8> {
+        self.doc.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, b: u8) -> Result<(), Unsupported> {
+        self.skip_whitespace();
+        match self.peek() == Some(b) {
+            true => {
+                self.pos += 1;
+                Ok(())
+            }
+            false => Err(Unsupported),
+        }
+    }
+
+    /// Parses a value. If `node` is not `None`, the value is located at this node of the path
+    /// tree.
+    fn value(&mut self, node: Option<&Node>, depth: usize) -> Result<(), Unsupported> {
+        self.skip_whitespace();
+        let start = self.pos;
+        match self.peek().ok_or(Unsupported)? {
+            b'{' => self.object(node, depth + 1)?,
+            b'[' => self.array(node, depth + 1)?,
+            b'"' => {
+                self.string()?;
+            }
+            b't' => self.literal(b"true")?,
+            b'f' => self.literal(b"false")?,
+            b'n' => self.literal(b"null")?,
+            b'-' | b'0'..=b'9' => self.number()?,
+            _ => return Err(Unsupported),
+        }
+        if let Some(node) = node {
+            for &idx in &node.terminals {
+                self.out[idx] = Some(start..self.pos);
+            }
+        }
+        Ok(())
+    }
+
+    fn object(&mut self, node: Option<&Node>, depth: usize) -> Result<(), Unsupported> {
+        if depth > MAX_DEPTH {
+            return Err(Unsupported);
+        }
+        self.pos += 1;
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(());
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some(b'"') {
+                return Err(Unsupported);
+            }
+            let start = self.pos;
+            let escaped = self.string()?;
+            let child = match node {
+                Some(node) if !node.children.is_empty() => {
+                    if escaped {
+                        return Err(Unsupported);
+                    }
+                    node.object_child(&self.doc[start + 1..self.pos - 1])
+                }
+                _ => None,
+            };
+            // NOTE: If a key occurs multiple times, serde_json uses the last value. Therefore,
+            // values found in earlier occurrences have to be discarded.
+            if let Some(child) = child {
+                for &idx in &child.subtree {
+                    self.out[idx] = None;
+                }
+            }
+            self.expect(b':')?;
+            self.value(child, depth)?;
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(());
+                }
+                _ => return Err(Unsupported),
+            }
+        }
+    }
+
+    fn array(&mut self, node: Option<&Node>, depth: usize) -> Result<(), Unsupported> {
+        if depth > MAX_DEPTH {
+            return Err(Unsupported);
+        }
+        self.pos += 1;
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(());
+        }
+        for index in 0.. {
+            let child = node.and_then(|n| n.array_child(index));
+            self.value(child, depth)?;
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => break,
+                _ => return Err(Unsupported),
+            }
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    /// Skips over a string. Returns whether the string contains escape sequences.
+    fn string(&mut self) -> Result<bool, Unsupported> {
+        self.pos += 1;
+        let mut escaped = false;
+        loop {
+            let rest = &self.doc[self.pos..];
+            let n = find_special(rest).ok_or(Unsupported)?;
+            self.pos += n;
+            match rest[n] {
+                b'"' => {
+                    self.pos += 1;
+                    return Ok(escaped);
+                }
+                b'\\' => {
+                    escaped = true;
+                    self.escape()?;
+                }
+                _ => return Err(Unsupported),
+            }
+        }
+    }
+
+    fn escape(&mut self) -> Result<(), Unsupported> {
+        let c = *self.doc.get(self.pos + 1).ok_or(Unsupported)?;
+        match c {
+            b'"' | b'\\' | b'/' | b'b' | b'f' | b'n' | b'r' | b't' => self.pos += 2,
+            b'u' => {
+                let hex = self
+                    .doc
+                    .get(self.pos + 2..self.pos + 6)
+                    .ok_or(Unsupported)?;
+                let hex = std::str::from_utf8(hex).map_err(|_| Unsupported)?;
+                if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+                    return Err(Unsupported);
+                }
+                let c = u16::from_str_radix(hex, 16).map_err(|_| Unsupported)?;
+                if (0xd800..0xe000).contains(&c) {
+                    return Err(Unsupported);
+                }
+                self.pos += 6;
+            }
+            _ => return Err(Unsupported),
+        }
+        Ok(())
+    }
+
+    fn literal(&mut self, literal: &[u8]) -> Result<(), Unsupported> {
+        match self.doc[self.pos..].starts_with(literal) {
+            true => {
+                self.pos += literal.len();
+                Ok(())
+            }
+            false => Err(Unsupported),
+        }
+    }
+
+    fn digits(&mut self) -> usize {
+        let n = self.doc[self.pos..]
+            .iter()
+            .take_while(|b| b.is_ascii_digit())
+            .count();
+        self.pos += n;
+        n
+    }
+
+    fn number(&mut self) -> Result<(), Unsupported> {
+        let start = self.pos;
+        if self.peek() == Some(b'-') {
+            self.pos += 1;
+        }
+        match self.peek() {
+            Some(b'0') => self.pos += 1,
+            Some(b'1'..=b'9') => {
+                self.digits();
+            }
+            _ => return Err(Unsupported),
+        }
+        if self.peek() == Some(b'.') {
+            self.pos += 1;
+            if self.digits() == 0 {
+                return Err(Unsupported);
+            }
+        }
+        let mut exponent = false;
+        if let Some(b'e' | b'E') = self.peek() {
+            exponent = true;
+            self.pos += 1;
+            if let Some(b'+' | b'-') = self.peek() {
+                self.pos += 1;
+            }
+            if self.digits() == 0 {
+                return Err(Unsupported);
+            }
+        }
+        // NOTE: serde_json rejects numbers that are out of the range of f64.
+        if exponent || self.pos - start > 300 {
+            let number =
+                std::str::from_utf8(&self.doc[start..self.pos]).map_err(|_| Unsupported)?;
+            match number.parse::<f64>() {
+                Ok(n) if n.is_finite() => {}
+                _ => return Err(Unsupported),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the position of the first byte that ends the plain part of a string, that is, a
+/// quote, a backslash, or a control character.
+fn find_special(s: &[u8]) -> Option<usize> {
+    const ONES: u64 = u64::MAX / 255;
+    const HIGH: u64 = ONES * 0x80;
+    let has_zero = |w: u64| w.wrapping_sub(ONES) & !w & HIGH != 0;
+    let mut i = 0;
+    // NOTE: Most strings are long enough for it to be worthwhile to check 8 bytes at a time.
+    while let Some(word) = s.get(i..i + 8) {
+        let w = u64::from_le_bytes(word.try_into().unwrap());
+        let control = w.wrapping_sub(ONES * 0x20) & !w & HIGH != 0;
+        if control || has_zero(w ^ (ONES * b'"' as u64)) || has_zero(w ^ (ONES * b'\\' as u64)) {
+            break;
+        }
+        i += 8;
+    }
+    s[i..]
+        .iter()
+        .position(|&b| b == b'"' || b == b'\\' || b < 0x20)
+        .map(|n| i + n)
+}
+
+/// Deserializes a value located at a node of the path tree. Values that are not on any path are
+/// replaced by null or omitted from their objects.
+struct Pruned<'a>(&'a Node);
+
+impl<'de> DeserializeSeed<'de> for Pruned<'_> {
+    type Value = Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        match self.0.terminals.is_empty() {
+            true => deserializer.deserialize_any(self),
+            false => Value::deserialize(deserializer),
+        }
+    }
+}
+
+impl<'de> Visitor<'de> for Pruned<'_> {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("any json value")
+    }
+
+    fn visit_bool<E>(self, v: bool) -> Result<Value, E> {
+        Ok(Value::Bool(v))
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<Value, E> {
+        Ok(Value::from(v))
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Value, E> {
+        Ok(Value::from(v))
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<Value, E> {
+        Ok(Number::from_f64(v).map_or(Value::Null, Value::Number))
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Value, E> {
+        Ok(Value::from(v))
+    }
+
+    fn visit_unit<E>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        // NOTE: The elements that are not on a path are replaced by null so that the indices of
+        // the other elements are preserved.
+        let mut values = vec![];
+        loop {
+            let value = match self.0.array_child(values.len()) {
+                Some(child) => seq.next_element_seed(Pruned(child))?,
+                None => seq.next_element_seed(Skip)?.map(|()| Value::Null),
+            };
+            match value {
+                Some(value) => values.push(value),
+                None => return Ok(Value::Array(values)),
+            }
+        }
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut values = Map::new();
+        while let Some(child) = map.next_key_seed(ChildKey(self.0))? {
+            match child {
+                // NOTE: Like serde_json, the last value of a duplicate key is used.
+                Some((key, child)) => {
+                    values.insert(key.to_string(), map.next_value_seed(Pruned(child))?);
+                }
+                None => map.next_value_seed(Skip)?,
+            }
+        }
+        Ok(Value::Object(values))
+    }
+}
+
+/// Deserializes an object key and returns the child of the node that it leads to.
+struct ChildKey<'a>(&'a Node);
+
+impl<'a, 'de> DeserializeSeed<'de> for ChildKey<'a> {
+    type Value = Option<(&'a str, &'a Node)>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_str(self)
+    }
+}
+
+impl<'a, 'de> Visitor<'de> for ChildKey<'a> {
+    type Value = Option<(&'a str, &'a Node)>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an object key")
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E> {
+        let (segment, child) = match self.0.children.iter().find(|(s, _)| s.key == v.as_bytes()) {
+            Some(child) => child,
+            None => return Ok(None),
+        };
+        // NOTE: The segments are created from strings.
+        let key = std::str::from_utf8(&segment.key).unwrap();
+        Ok(Some((key, child)))
+    }
+}
+
+/// Validates a value without building it. Unlike `serde::de::IgnoredAny`, this rejects the same
+/// values as deserializing a `serde_json::Value`, e.g. strings containing unpaired surrogates
+/// and numbers that are out of range.
+struct Skip;
+
+impl<'de> DeserializeSeed<'de> for Skip {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for Skip {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("any json value")
+    }
+
+    fn visit_bool<E>(self, _: bool) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_i64<E>(self, _: i64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_u64<E>(self, _: u64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_f64<E>(self, _: f64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_str<E>(self, _: &str) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_unit<E>(self) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while seq.next_element_seed(Skip)?.is_some() {}
+        Ok(())
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        while map.next_key_seed(SkipKey)?.is_some() {
+            map.next_value_seed(Skip)?;
+        }
+        Ok(())
+    }
+}
+
+/// Validates an object key without building it.
+struct SkipKey;
+
+impl<'de> DeserializeSeed<'de> for SkipKey {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_str(Skip)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distinct::{hash_raw, hash_value};
+    use crate::test_util::Rng;
+
+    const PATHS: [&str; 8] = ["type", "a.b", "/a/0", "b", "a", "/0", "a.b.c", "/1/a"];
+
+    /// Scans a document and checks that every value found is the same as the one found by
+    /// looking up the path in the document parsed by serde_json. Also checks that
+    /// `Scanner::parse` agrees with serde_json.
+    fn check(doc: &str, paths: &[&str]) -> Result<(), Unsupported> {
+        let paths: Vec<FieldPath> = paths.iter().map(|p| p.parse().unwrap()).collect();
+        let scanner = Scanner::new(&paths);
+        let parsed = serde_json::from_str::<Value>(doc);
+        let pruned = scanner.parse(doc);
+        assert_eq!(parsed.is_ok(), pruned.is_ok(), "{}", doc);
+        if let (Ok(full), Ok(pruned)) = (&parsed, &pruned) {
+            for path in &paths {
+                assert_eq!(
+                    path.lookup(full),
+                    path.lookup(pruned),
+                    "{} in {}",
+                    path,
+                    doc
+                );
+            }
+        }
+        let mut out = vec![];
+        scanner.scan(doc.as_bytes(), &mut out)?;
+        let full = match parsed {
+            Ok(full) => full,
+            Err(e) => panic!("scanned {} which serde_json rejects: {}", doc, e),
+        };
+        for (path, range) in paths.iter().zip(out) {
+            let raw = range.map(|r| &doc.as_bytes()[r]);
+            let scanned = raw.map(|raw| serde_json::from_slice::<Value>(raw).unwrap());
+            let expected = path.lookup(&full);
+            assert_eq!(scanned.as_ref(), expected, "{} in {}", path, doc);
+            if let (Some(raw), Some(expected)) = (raw, expected) {
+                assert_eq!(
+                    hash_raw(raw),
+                    Some(hash_value(expected)),
+                    "{} in {}",
+                    path,
+                    doc
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn supported(doc: &str) {
+        assert!(check(doc, &PATHS).is_ok(), "{} was not scanned", doc);
+    }
+
+    fn unsupported(doc: &str) {
+        assert!(check(doc, &PATHS).is_err(), "{} was scanned", doc);
+    }
+
+    #[test]
+    fn simple() {
+        supported(r#"{"type":"a"}"#);
+        supported(r#"{"type":"a","a":{"b":[1,2]},"b":null}"#);
+        supported(r#"{"a":[{"x":1},true],"type":{"nested":["value"]}}"#);
+        supported(r#"{"other":1}"#);
+        supported(r#"{}"#);
+    }
+
+    #[test]
+    fn duplicate_keys() {
+        supported(r#"{"type":"a","type":"b"}"#);
+        supported(r#"{"a":{"b":1},"a":2}"#);
+        supported(r#"{"a":{"b":{"c":1}},"a":{"x":2}}"#);
+        supported(r#"{"a":[1],"a":{"0":2}}"#);
+    }
+
+    #[test]
+    fn escaped_keys() {
+        // NOTE: Escaped keys are only a problem if they are compared with a path segment.
+        supported(r#"{"typex":1,"type":"a"}"#);
+        supported(r#"{"a":{"x":{"\n":1},"b":2}}"#);
+        supported(r#"{"type":{"\"":1}}"#);
+        unsupported(r#"{"typ\u0065":"a"}"#);
+        unsupported(r#"{"a":{"\u0062":1}}"#);
+        unsupported(r#"{"x\"":1,"type":"a"}"#);
+    }
+
+    #[test]
+    fn escaped_values() {
+        supported(r#"{"type":"a\"b\\c\/d\b\f\n\r\t"}"#);
+        supported(r#"{"type":"\u00e9\u0000\uffff"}"#);
+        supported(r#"{"type":"é😀"}"#);
+    }
+
+    #[test]
+    fn surrogates() {
+        unsupported(r#"{"type":"\ud83d\ude00"}"#);
+        unsupported(r#"{"type":"\ud800"}"#);
+        unsupported(r#"{"type":"\udc00"}"#);
+        unsupported(r#"{"x":"\ud800","type":"a"}"#);
+        unsupported(r#"{"type":"\ud800A"}"#);
+    }
+
+    #[test]
+    fn nesting() {
+        // NOTE: The object counts as one level.
+        for depth in [1, 64, MAX_DEPTH - 2, MAX_DEPTH - 1] {
+            let doc = format!(r#"{{"a":{}1{}}}"#, "[".repeat(depth), "]".repeat(depth));
+            supported(&doc);
+        }
+        for depth in [MAX_DEPTH, 127, 128, 129, 1000] {
+            let doc = format!(r#"{{"a":{}1{}}}"#, "[".repeat(depth), "]".repeat(depth));
+            unsupported(&doc);
+            let doc = format!(
+                r#"{}{{"a":1}}{}"#,
+                "{\"x\":".repeat(depth),
+                "}".repeat(depth)
+            );
+            unsupported(&doc);
+        }
+    }
+
+    #[test]
+    fn numbers() {
+        for n in [
+            "0",
+            "-0",
+            "1",
+            "-1",
+            "0.5",
+            "-0.0",
+            "1e5",
+            "1E+5",
+            "1e-5",
+            "2.5e-308",
+            "1e-400",
+            "18446744073709551615",
+            "18446744073709551616",
+            "-9223372036854775808",
+            "-9223372036854775809",
+            "123456789012345678901234567890",
+            "1.7976931348623157e308",
+        ] {
+            supported(&format!(r#"{{"type":{},"a":[{}]}}"#, n, n));
+        }
+        let long = format!("1{}", "0".repeat(305));
+        supported(&format!(r#"{{"type":{}}}"#, long));
+        for n in [
+            "1e400",
+            "-1e400",
+            "1.8e308",
+            &format!("1{}", "0".repeat(400)),
+        ] {
+            unsupported(&format!(r#"{{"type":{}}}"#, n));
+        }
+    }
+
+    #[test]
+    fn whitespace() {
+        supported(" \t\r\n{ \"type\" :\t\"a\" , \"a\" : [ 1 , { \"b\" : 2 } ] } \n\t ");
+        supported("{\"type\":\n\"a\"}");
+    }
+
+    #[test]
+    fn top_level_values() {
+        for doc in [
+            "null",
+            "true",
+            "1",
+            "\"type\"",
+            "[]",
+            "[1,{\"a\":2}]",
+            "[{\"a\":1}]",
+        ] {
+            supported(doc);
+        }
+    }
+
+    #[test]
+    fn invalid_documents() {
+        for doc in [
+            "",
+            " ",
+            r#"{"type":"a"} x"#,
+            r#"{"type":"a"}}"#,
+            r#"{"type":"a"} {}"#,
+            r#"{"type":"a"}x"#,
+            r#"{"type":"a""#,
+            r#"{"type" "a"}"#,
+            r#"{"type":"a" "b":1}"#,
+            r#"{"type":"a",}"#,
+            r#"{type:"a"}"#,
+            r#"{'type':"a"}"#,
+            r#"{"type":[1 2]}"#,
+            r#"{"type":[1,]}"#,
+            r#"{"type":[1"#,
+            r#"{"type":"a"#,
+            "{\"type\":\"a\tb\"}",
+            "{\"type\":\"a\u{1}\"}",
+            r#"{"type":"\x"}"#,
+            r#"{"type":"\"#,
+            r#"{"type":"\u12"}"#,
+            r#"{"type":"\u12g4"}"#,
+            r#"{"type":"\u123é"}"#,
+            r#"{"type":"\u+123"}"#,
+            r#"{"type":tru}"#,
+            r#"{"type":nul}"#,
+            r#"{"type":True}"#,
+            r#"{"type":-}"#,
+            r#"{"type":+1}"#,
+            r#"{"type":01}"#,
+            r#"{"type":1.}"#,
+            r#"{"type":.5}"#,
+            r#"{"type":1e}"#,
+            r#"{"type":1e+}"#,
+            r#"{"type":0x10}"#,
+            r#"{"type":NaN}"#,
+            r#"{"type":Infinity}"#,
+        ] {
+            unsupported(doc);
+        }
+    }
+
+    /// Checks random documents and mutations of them.
+    #[test]
+    fn random_documents() {
+        let mut rng = Rng::new(1);
+        let mut scanned = [0; 2];
+        for _ in 0..20_000 {
+            let mut doc = String::new();
+            random_value(&mut rng, &mut doc, 0);
+            scanned[0] += check(&doc, &PATHS).is_ok() as usize;
+            let mut bytes = doc.into_bytes();
+            for _ in 0..rng.below(3) + 1 {
+                let pos = rng.below(bytes.len() as u64 + 1) as usize;
+                let chars = b"{}[]\":,\\ 0-.eu";
+                let byte = rng.pick(chars);
+                match rng.below(3) {
+                    0 if pos < bytes.len() => {
+                        bytes.remove(pos);
+                    }
+                    1 if pos < bytes.len() => bytes[pos] = byte,
+                    _ => bytes.insert(pos, byte),
+                }
+            }
+            if let Ok(doc) = String::from_utf8(bytes) {
+                scanned[1] += check(&doc, &PATHS).is_ok() as usize;
+            }
+        }
+        // NOTE: Make sure that the fast path is actually tested.
+        assert!(scanned[0] > 5_000 && scanned[1] > 1_000, "{:?}", scanned);
+    }
+
+    fn random_whitespace(rng: &mut Rng, doc: &mut String) {
+        doc.push_str(rng.pick(&["", "", "", " ", "\t", "\r\n", "  "]));
+    }
+
+    fn random_value(rng: &mut Rng, doc: &mut String, depth: usize) {
+        random_whitespace(rng, doc);
+        let kind = match depth {
+            0 => 5,
+            4.. => rng.below(4),
+            _ => rng.below(6),
+        };
+        match kind {
+            0 => doc.push_str(rng.pick(&["null", "true", "false"])),
+            1 => doc.push_str(rng.pick(&[
+                "0",
+                "-0",
+                "1",
+                "-12",
+                "0.5",
+                "1e3",
+                "2.5E-2",
+                "1.0",
+                "18446744073709551616",
+                "-9223372036854775809",
+                "1e400",
+            ])),
+            2 | 3 => doc.push_str(rng.pick(&[
+                r#""""#,
+                r#""a""#,
+                r#""type""#,
+                r#""a""#,
+                r#""\n\"\\""#,
+                r#""\u00e9""#,
+                r#""\ud83d\ude00""#,
+                r#""é😀""#,
+                r#""\ud800""#,
+            ])),
+            4 => {
+                doc.push('[');
+                for i in 0..rng.below(4) {
+                    if i > 0 {
+                        doc.push(',');
+                    }
+                    random_value(rng, doc, depth + 1);
+                }
+                random_whitespace(rng, doc);
+                doc.push(']');
+            }
+            _ => {
+                doc.push('{');
+                for i in 0..rng.below(5) {
+                    if i > 0 {
+                        doc.push(',');
+                    }
+                    random_whitespace(rng, doc);
+                    doc.push_str(rng.pick(&[
+                        r#""type""#,
+                        r#""a""#,
+                        r#""b""#,
+                        r#""c""#,
+                        r#""0""#,
+                        r#""1""#,
+                        r#""x""#,
+                        r#""type""#,
+                        r#""a""#,
+                    ]));
+                    random_whitespace(rng, doc);
+                    doc.push(':');
+                    random_value(rng, doc, depth + 1);
+                }
+                random_whitespace(rng, doc);
+                doc.push('}');
+            }
+        }
+        random_whitespace(rng, doc);
+    }
+}
@@ -0,0 +1,288 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+
+/// The maximum number of example values recorded per field.
+const MAX_EXAMPLES: usize = 3;
+
+/// The kind of a json value. Integers and other numbers are distinguished as in JSON Schema.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Kind {
+    Null,
+    Boolean,
+    Integer,
+    Number,
+    String,
+    Array,
+    Object,
+}
+
+impl Kind {
+    pub const ALL: [Kind; 7] = [
+        Kind::Null,
+        Kind::Boolean,
+        Kind::Integer,
+        Kind::Number,
+        Kind::String,
+        Kind::Array,
+        Kind::Object,
+    ];
+
+    fn of(value: &Value) -> Self {
+        match value {
+            Value::Null => Kind::Null,
+            Value::Bool(_) => Kind::Boolean,
+            Value::Number(n) if n.is_f64() => Kind::Number,
+            Value::Number(_) => Kind::Integer,
+            Value::String(_) => Kind::String,
+            Value::Array(_) => Kind::Array,
+            Value::Object(_) => Kind::Object,
+        }
+    }
+
+    /// The name of the kind as used by JSON Schema.
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Null => "null",
+            Kind::Boolean => "boolean",
+            Kind::Integer => "integer",
+            Kind::Number => "number",
+            Kind::String => "string",
+            Kind::Array => "array",
+            Kind::Object => "object",
+        }
+    }
+}
+
+/// The fields that occur in a set of entries.
+///
+/// As long as schemas are merged in the order of the entries, the examples are the first distinct
+/// values in the input.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Schema {
+    /// The node of the entries themselves.
+    root: Node,
+}
+
+/// A field and the fields nested in it.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct Node {
+    /// The number of entries in which the field occurs.
+    present: u64,
+    /// The number of entries in which the field has a value of each kind, indexed by `Kind`.
+    kinds: [u64; Kind::ALL.len()],
+    /// Distinct values of the field that are neither arrays nor objects.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    examples: Vec<Value>,
+    /// The number of the last entry in which the field occurred. This is used to count entries
+    /// only once if the field occurs multiple times in an array.
+    #[serde(skip)]
+    last_entry: u64,
+    /// The kinds observed in the last entry as a bit set.
+    #[serde(skip)]
+    last_kinds: u8,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    fields: BTreeMap<String, Node>,
+    /// The elements of arrays.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    items: Option<Box<Node>>,
+}
+
+/// A field as reported by [`Schema::fields`].
+#[non_exhaustive]
+pub struct Field<'a> {
+    /// The path of the field as a JSON Pointer in which the elements of arrays are represented by
+    /// the segment `*`.
+    pub path: String,
+    /// The number of entries in which the field occurs.
+    pub present: u64,
+    /// The number of entries in which the field has a value of a kind.
+    pub kinds: Vec<(Kind, u64)>,
+    pub examples: &'a [Value],
+}
+
+impl Schema {
+    pub(crate) fn add(&mut self, entry: &Value) {
+        let n = self.root.present + 1;
+        self.root.add(entry, n);
+    }
+
+    pub(crate) fn merge(&mut self, other: &Schema) {
+        self.root.merge(&other.root);
+    }
+
+    /// The number of entries.
+    pub fn entries(&self) -> u64 {
+        self.root.present
+    }
+
+    /// Returns all fields in depth-first order. Fields are ordered by their names.
+    pub fn fields(&self) -> Vec<Field<'_>> {
+        let mut fields = vec![];
+        self.root.collect_fields(&mut String::new(), &mut fields);
+        fields
+    }
+
+    /// Returns a JSON Schema (draft 2020-12) that the entries satisfy.
+    ///
+    /// Fields are required if they occur in every object containing them, except in arrays.
+    pub fn json_schema(&self) -> Map<String, Value> {
+        self.root.json_schema(false)
+    }
+}
+
+impl Node {
+    fn add(&mut self, value: &Value, entry: u64) {
+        if self.last_entry != entry {
+            self.last_entry = entry;
+            self.last_kinds = 0;
+            self.present += 1;
+        }
+        let kind = Kind::of(value);
+        if self.last_kinds & (1 << kind as u8) == 0 {
+            self.last_kinds |= 1 << kind as u8;
+            self.kinds[kind as usize] += 1;
+        }
+        match value {
+            Value::Object(o) => {
+                for (k, v) in o {
+                    // NOTE: This avoids allocating the key for fields that have been seen before.
+                    let field = match self.fields.get_mut(k) {
+                        Some(field) => field,
+                        None => self.fields.entry(k.clone()).or_default(),
+                    };
+                    field.add(v, entry);
+                }
+            }
+            Value::Array(a) => {
+                let items = self.items.get_or_insert_with(Default::default);
+                for v in a {
+                    items.add(v, entry);
+                }
+            }
+            _ => {
+                if self.examples.len() < MAX_EXAMPLES && !self.examples.contains(value) {
+                    self.examples.push(value.clone());
+                }
+            }
+        }
+    }
+
+    fn merge(&mut self, other: &Node) {
+        self.present += other.present;
+        for (n, other) in self.kinds.iter_mut().zip(other.kinds) {
+            *n += other;
+        }
+        for value in &other.examples {
+            if self.examples.len() >= MAX_EXAMPLES {
+                break;
+            }
+            if !self.examples.contains(value) {
+                self.examples.push(value.clone());
+            }
+        }
+        for (k, v) in &other.fields {
+            match self.fields.get_mut(k) {
+                Some(field) => field.merge(v),
+                None => {
+                    self.fields.insert(k.clone(), v.clone());
+                }
+            }
+        }
+        if let Some(other) = &other.items {
+            match &mut self.items {
+                Some(items) => items.merge(other),
+                None => self.items = Some(other.clone()),
+            }
+        }
+    }
+
+    fn kinds(&self) -> Vec<(Kind, u64)> {
+        Kind::ALL
+            .iter()
+            .map(|&k| (k, self.kinds[k as usize]))
+            .filter(|&(_, n)| n > 0)
+            .collect()
+    }
+
+    /// Appends the fields nested in this node. `path` is the path of this node.
+    fn collect_fields<'a>(&'a self, path: &mut String, fields: &mut Vec<Field<'a>>) {
+        let len = path.len();
+        let children = self
+            .fields
+            .iter()
+            .map(|(k, v)| (escape_pointer_segment(k), v))
+            .chain(self.items.as_deref().map(|v| ("*".to_string(), v)));
+        for (segment, node) in children {
+            path.push('/');
+            path.push_str(&segment);
+            fields.push(Field {
+                path: path.clone(),
+                present: node.present,
+                kinds: node.kinds(),
+                examples: &node.examples,
+            });
+            node.collect_fields(path, fields);
+            path.truncate(len);
+        }
+    }
+
+    /// `in_array` is true if this node describes the elements of an array or a field nested in
+    /// them. In that case, the counts refer to entries instead of objects and cannot be used to
+    /// decide whether a field is required.
+    fn json_schema(&self, in_array: bool) -> Map<String, Value> {
+        let mut schema = Map::new();
+        let has = |kind: Kind| self.kinds[kind as usize] > 0;
+        let mut types: Vec<_> = Kind::ALL
+            .iter()
+            .filter(|&&k| has(k))
+            // NOTE: In JSON Schema, integers are also numbers.
+            .filter(|&&k| !(k == Kind::Integer && has(Kind::Number)))
+            .map(|k| Value::from(k.name()))
+            .collect();
+        match types.len() {
+            0 => {}
+            1 => {
+                schema.insert("type".to_string(), types.remove(0));
+            }
+            _ => {
+                schema.insert("type".to_string(), Value::Array(types));
+            }
+        }
+        if has(Kind::Object) {
+            let properties: Map<_, _> = self
+                .fields
+                .iter()
+                .map(|(k, v)| (k.clone(), Value::Object(v.json_schema(in_array))))
+                .collect();
+            schema.insert("properties".to_string(), Value::Object(properties));
+            if !in_array {
+                let objects = self.kinds[Kind::Object as usize];
+                let required: Vec<_> = self
+                    .fields
+                    .iter()
+                    .filter(|(_, v)| v.present == objects)
+                    .map(|(k, _)| Value::from(k.as_str()))
+                    .collect();
+                if !required.is_empty() {
+                    schema.insert("required".to_string(), Value::Array(required));
+                }
+            }
+        }
+        if let Some(items) = &self.items {
+            let items = items.json_schema(true);
+            schema.insert("items".to_string(), Value::Object(items));
+        }
+        if !self.examples.is_empty() {
+            let examples = self.examples.clone();
+            schema.insert("examples".to_string(), Value::Array(examples));
+        }
+        schema
+    }
+}
+
+/// Escapes a segment of a JSON Pointer as described in RFC 6901.
+fn escape_pointer_segment(s: &str) -> String {
+    s.replace('~', "~0").replace('/', "~1")
+}
@@ -0,0 +1,173 @@
+use std::ops::Range;
+
+/// Finds the lines of a file whose timestamps lie in the range `since..until`.
+///
+/// The lines must be sorted by their timestamps. `timestamp` returns the timestamp of a line
+/// (including its terminator) or `None` if the line does not contain a valid timestamp. Such
+/// lines are assumed to belong to the next line with a valid timestamp.
+///
+/// Returns the byte range of the lines, which starts and ends at line boundaries.
+pub fn find_window(
+    data: &[u8],
+    since: Option<i64>,
+    until: Option<i64>,
+    timestamp: impl Fn(&[u8]) -> Option<i64>,
+) -> Range<usize> {
+    let start = match since {
+        Some(since) => partition_point(data, 0, |line| timestamp(line).map(|t| t < since)),
+        None => 0,
+    };
+    let end = match until {
+        Some(until) => partition_point(data, start, |line| timestamp(line).map(|t| t < until)),
+        None => data.len(),
+    };
+    start..end
+}
+
+/// Returns the start of the first line at or after `lo` for which `pred` returns `false`.
+///
+/// Lines for which `pred` returns `None` are skipped during the search.
+fn partition_point(data: &[u8], mut lo: usize, pred: impl Fn(&[u8]) -> Option<bool>) -> usize {
+    // NOTE: All lines starting before `lo` satisfy the predicate and no line starting at or after
+    // `hi` does. Both are always line boundaries.
+    let mut hi = data.len();
+    while lo < hi {
+        let mut pos = next_line_start(data, lo + (hi - lo) / 2);
+        if pos >= hi {
+            pos = lo;
+        }
+        let probe_start = pos;
+        let mut satisfied = None;
+        while pos < hi {
+            let end = next_line_start(data, pos + 1);
+            if let Some(p) = pred(&data[pos..end]) {
+                satisfied = Some(p);
+                break;
+            }
+            pos = end;
+        }
+        match satisfied {
+            Some(true) => lo = next_line_start(data, pos + 1),
+            _ => hi = probe_start,
+        }
+    }
+    lo
+}
+
+/// Returns the start of the first line starting at or after `pos`.
+fn next_line_start(data: &[u8], pos: usize) -> usize {
+    if pos == 0 || pos >= data.len() {
+        return pos.min(data.len());
+    }
+    match data[pos - 1] {
+        b'\n' => pos,
+        _ => memchr::memchr(b'\n', &data[pos..]).map_or(data.len(), |n| pos + n + 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::Rng;
+
+    fn timestamp(line: &[u8]) -> Option<i64> {
+        std::str::from_utf8(line).ok()?.trim_end().parse().ok()
+    }
+
+    /// Finds the window by assigning every line the timestamp of the next line with a valid one.
+    fn linear(data: &[u8], since: Option<i64>, until: Option<i64>) -> Range<usize> {
+        let mut lines = vec![];
+        let mut start = 0;
+        while start < data.len() {
+            let end = next_line_start(data, start + 1);
+            lines.push((start, timestamp(&data[start..end])));
+            start = end;
+        }
+        let mut next = None;
+        for (_, t) in lines.iter_mut().rev() {
+            match t {
+                Some(_) => next = *t,
+                None => *t = next,
+            }
+        }
+        let find = |bound: Option<i64>, default: usize| match bound {
+            Some(bound) => lines
+                .iter()
+                .find(|(_, t)| t.is_none_or(|t| t >= bound))
+                .map_or(data.len(), |(start, _)| *start),
+            None => default,
+        };
+        let start = find(since, 0);
+        let end = find(until, data.len()).max(start);
+        start..end
+    }
+
+    fn check(data: &str) {
+        let data = data.as_bytes();
+        for since in (-1..12).map(Some).chain([None]) {
+            for until in (-1..12).map(Some).chain([None]) {
+                assert_eq!(
+                    find_window(data, since, until, timestamp),
+                    linear(data, since, until),
+                    "{:?}..{:?} in {:?}",
+                    since,
+                    until,
+                    String::from_utf8_lossy(data),
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn sorted_lines() {
+        check("");
+        check("5");
+        check("5\n");
+        check("1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n");
+        check("1\n1\n1\n4\n4\n10\n10");
+        check("0\n0\n0\n0\n0\n0\n0\n0\n0\n0\n0\n0\n");
+    }
+
+    #[test]
+    fn lines_without_timestamps() {
+        check("x\n");
+        check("x\ny\nz");
+        check("x\n1\ny\n2\nz\n");
+        check("1\n2\nx\ny\nz\nw\n8\n9\nx");
+        check("\n\n3\n\n\n\n7\n\n");
+    }
+
+    #[test]
+    fn generated_lines() {
+        let mut rng = Rng::new(0x853c49e6748fea9b);
+        let mut next = |n| rng.below(n);
+        for _ in 0..300 {
+            let mut data = String::new();
+            let mut t = 0;
+            for _ in 0..next(30) {
+                t += next(2);
+                match next(4) {
+                    0 => data.push_str("invalid"),
+                    _ => data.push_str(&t.to_string()),
+                }
+                data.push('\n');
+            }
+            if next(2) == 0 {
+                data.pop();
+            }
+            check(&data);
+        }
+    }
+
+    #[test]
+    fn line_boundaries() {
+        let data = b"abc\ndef\n\nghi";
+        assert_eq!(next_line_start(data, 0), 0);
+        assert_eq!(next_line_start(data, 1), 4);
+        assert_eq!(next_line_start(data, 4), 4);
+        assert_eq!(next_line_start(data, 8), 8);
+        assert_eq!(next_line_start(data, 9), 9);
+        assert_eq!(next_line_start(data, 10), data.len());
+        assert_eq!(next_line_start(data, 100), data.len());
+    }
+}
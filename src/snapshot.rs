@@ -0,0 +1,370 @@
+use crate::analyzer::Options;
+use crate::checkpoint::Checkpoints;
+use crate::input::{self, Input};
+use crate::report::Report;
+use crate::stats::{ByteMode, Stats};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::ffi::OsStr;
+use std::io::{Read, Write};
+
+/// Identifies snapshot files.
+const SNAPSHOT_FORMAT: &str = "log-analyzer snapshot";
+
+/// The version of the snapshot format. This is incremented whenever the format changes.
+const SNAPSHOT_VERSION: u32 = 5;
+
+/// The aggregated state of an analysis.
+///
+/// Snapshots can be stored and merged with the snapshots of other analyses, e.g. of the logs of
+/// other hosts, to obtain the report of all inputs without reprocessing them.
+///
+/// Counts, sizes, value statistics, and distinct counts merge associatively and commutatively, so
+/// the report does not depend on the order in which snapshots are merged (up to floating-point
+/// rounding of the value statistics). Only the locations of skipped lines and the example values
+/// of fields are taken from earlier snapshots first.
+///
+/// Snapshots of analyses of files also record how far each file has been processed so that the
+/// analysis can be resumed with [`Analyzer::resume`].
+///
+/// [`Analyzer::resume`]: crate::Analyzer::resume
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// Always [`SNAPSHOT_FORMAT`].
+    format: String,
+    /// Always [`SNAPSHOT_VERSION`] in snapshots that were read successfully.
+    version: u32,
+    pub(crate) settings: Settings,
+    pub(crate) stats: Stats,
+    /// `None` if the state includes inputs other than files, such as stdin, or if the snapshot
+    /// was merged.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) checkpoints: Option<Checkpoints>,
+}
+
+/// The options that determine the meaning of the aggregated state. Only snapshots with the same
+/// settings can be merged.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Settings {
+    /// The names of the components of the group keys.
+    key_fields: Vec<String>,
+    /// Whether the last component of the group keys is the start of a time bucket.
+    time_buckets: bool,
+    value_field: Option<String>,
+    exact_percentiles: bool,
+    distinct_field: Option<String>,
+    exact_distinct: bool,
+    bytes: ByteMode,
+    count_compressed: bool,
+    /// Whether the fields of the entries are collected.
+    schema: bool,
+}
+
+impl Settings {
+    pub(crate) fn new(options: &Options) -> Self {
+        Self {
+            key_fields: options.key_fields(),
+            time_buckets: options.bucket.is_some(),
+            value_field: options.value.as_ref().map(|v| v.to_string()),
+            exact_percentiles: options.exact_percentiles,
+            distinct_field: options.distinct.as_ref().map(|v| v.to_string()),
+            exact_distinct: options.exact_distinct,
+            bytes: options.bytes,
+            count_compressed: options.count_compressed,
+            schema: options.schema,
+        }
+    }
+
+    pub(crate) fn report(&self, stats: &Stats) -> Report {
+        Report::new(
+            self.key_fields.clone(),
+            self.time_buckets,
+            self.value_field.clone(),
+            self.distinct_field.clone(),
+            self.bytes,
+            self.count_compressed,
+            stats,
+        )
+    }
+
+    /// Returns a description of the first setting that differs from `other`.
+    fn difference(&self, other: &Settings) -> Option<&'static str> {
+        let differences = [
+            (self.key_fields != other.key_fields, "key fields"),
+            (self.time_buckets != other.time_buckets, "time buckets"),
+            (self.value_field != other.value_field, "value field"),
+            (
+                self.exact_percentiles != other.exact_percentiles,
+                "exactness of the percentiles",
+            ),
+            (
+                self.distinct_field != other.distinct_field,
+                "distinct field",
+            ),
+            (
+                self.exact_distinct != other.exact_distinct,
+                "exactness of the distinct counts",
+            ),
+            (self.bytes != other.bytes, "byte accounting"),
+            (
+                self.count_compressed != other.count_compressed,
+                "counting of compressed bytes",
+            ),
+            (self.schema != other.schema, "collection of the fields"),
+        ];
+        differences
+            .into_iter()
+            .find(|&(differs, _)| differs)
+            .map(|(_, setting)| setting)
+    }
+}
+
+impl Snapshot {
+    pub(crate) fn new(settings: Settings, stats: Stats, checkpoints: Option<Checkpoints>) -> Self {
+        Self {
+            format: SNAPSHOT_FORMAT.to_string(),
+            version: SNAPSHOT_VERSION,
+            settings,
+            stats,
+            checkpoints,
+        }
+    }
+
+    /// Reads a snapshot written by [`Snapshot::write`]. `-` refers to stdin.
+    pub fn read(path: &OsStr) -> Result<Self> {
+        match input::open(path, false)? {
+            Input::Stream(stream) => Self::parse(stream.reader),
+            Input::Mapped(_) => unreachable!("memory maps are disabled"),
+        }
+    }
+
+    /// Reads a snapshot written by [`Snapshot::write`] from a reader.
+    pub fn from_reader(reader: impl Read + Send) -> Result<Self> {
+        let stream = input::open_reader(reader).context("Could not read the snapshot")?;
+        Self::parse(stream.reader)
+    }
+
+    fn parse(reader: impl Read) -> Result<Self> {
+        let snapshot: Snapshot =
+            serde_json::from_reader(reader).context("The input is not a valid snapshot")?;
+        if snapshot.format != SNAPSHOT_FORMAT {
+            bail!("The input is not a snapshot");
+        }
+        if snapshot.version != SNAPSHOT_VERSION {
+            bail!(
+                "The snapshot has version {} but only version {} is supported",
+                snapshot.version,
+                SNAPSHOT_VERSION
+            );
+        }
+        // NOTE: The quantiles of a snapshot that was edited or corrupted could be computed from
+        // the wrong values or silently become approximate, so such snapshots are rejected here.
+        let exact = snapshot.settings.exact_percentiles;
+        let types = &snapshot.stats.types;
+        if !types.values().all(|data| data.values.is_consistent(exact)) {
+            bail!("The snapshot contains value statistics that do not match its settings");
+        }
+        Ok(snapshot)
+    }
+
+    /// Writes the snapshot as zstd-compressed json.
+    pub fn write(&self, w: impl Write) -> Result<()> {
+        let mut encoder = zstd::stream::write::Encoder::new(w, 0)?;
+        serde_json::to_writer(&mut encoder, self)?;
+        encoder.finish()?.flush()?;
+        Ok(())
+    }
+
+    /// Adds the state of `other`. Fails if the snapshots were created with different key fields,
+    /// value fields, distinct fields, or other options that affect the aggregated state.
+    ///
+    /// Merged snapshots cannot be resumed.
+    pub fn merge(&mut self, other: Snapshot) -> Result<()> {
+        if let Some(setting) = self.settings.difference(&other.settings) {
+            bail!("The snapshots differ in the {}", setting);
+        }
+        self.stats.merge(other.stats);
+        self.checkpoints = None;
+        Ok(())
+    }
+
+    /// Returns the report of the aggregated state. The types are sorted by their keys.
+    pub fn report(&self) -> Report {
+        self.settings.report(&self.stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::Analyzer;
+    use crate::report::Format;
+    use serde_json::{json, Value};
+
+    const LINES: [&str; 6] = [
+        r#"{"type":"a","v":1,"u":"x"}"#,
+        r#"{"type":"b","v":2.5,"u":"y"}"#,
+        r#"{"type":"a","v":3,"u":"y"}"#,
+        r#"{"type":"c"}"#,
+        r#"{"type":"a","v":-1,"u":"x"}"#,
+        r#"{"type":"b","v":10,"u":"z"}"#,
+    ];
+
+    fn analyze(lines: &[&str], exact: bool) -> Analyzer {
+        let mut analyzer = Analyzer::builder()
+            .value("v".parse().unwrap())
+            .exact_percentiles(exact)
+            .distinct("u".parse().unwrap())
+            .build()
+            .unwrap();
+        for line in lines {
+            analyzer.feed_line(line.as_bytes()).unwrap();
+        }
+        analyzer
+    }
+
+    fn json_report(report: &Report) -> String {
+        let mut out = vec![];
+        report.write(Format::Json, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn write(snapshot: &Snapshot) -> Vec<u8> {
+        let mut out = vec![];
+        snapshot.write(&mut out).unwrap();
+        out
+    }
+
+    /// Serializes a snapshot as plain json so that it can be modified.
+    fn to_json(snapshot: &Snapshot) -> Value {
+        serde_json::to_value(snapshot).unwrap()
+    }
+
+    fn from_json(json: &Value) -> Result<Snapshot> {
+        Snapshot::from_reader(&serde_json::to_vec(json).unwrap()[..])
+    }
+
+    fn error(result: Result<Snapshot>) -> String {
+        match result {
+            Ok(_) => panic!("the snapshot was accepted"),
+            Err(e) => format!("{:#}", e),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        for exact in [false, true] {
+            let analyzer = analyze(&LINES, exact);
+            let snapshot = analyzer.snapshot();
+            let data = write(&snapshot);
+            // NOTE: The snapshot is compressed.
+            assert!(data.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]));
+            let read = Snapshot::from_reader(&data[..]).unwrap();
+            assert_eq!(read.settings, snapshot.settings);
+            assert_eq!(json_report(&read.report()), json_report(&analyzer.report()));
+            assert_eq!(write(&read), data);
+        }
+    }
+
+    #[test]
+    fn merge() {
+        let (l, r) = LINES.split_at(2);
+        let mut merged = analyze(l, false).snapshot();
+        merged.merge(analyze(r, false).snapshot()).unwrap();
+        assert!(merged.checkpoints.is_none());
+        assert_eq!(
+            json_report(&merged.report()),
+            json_report(&analyze(&LINES, false).report())
+        );
+        let mut empty = analyze(&[], false).snapshot();
+        empty.merge(analyze(&LINES, false).snapshot()).unwrap();
+        assert_eq!(
+            json_report(&empty.report()),
+            json_report(&analyze(&LINES, false).report())
+        );
+    }
+
+    #[test]
+    fn merge_requires_the_same_settings() {
+        let mut snapshot = analyze(&LINES, false).snapshot();
+        let exact = analyze(&LINES, true).snapshot();
+        let e = snapshot.merge(exact).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "The snapshots differ in the exactness of the percentiles"
+        );
+        let other_key = Analyzer::builder()
+            .key("kind".parse().unwrap())
+            .value("v".parse().unwrap())
+            .distinct("u".parse().unwrap())
+            .build()
+            .unwrap()
+            .snapshot();
+        let e = snapshot.merge(other_key).unwrap_err();
+        assert_eq!(e.to_string(), "The snapshots differ in the key fields");
+        let no_value = Analyzer::builder().build().unwrap().snapshot();
+        let e = snapshot.merge(no_value).unwrap_err();
+        assert_eq!(e.to_string(), "The snapshots differ in the value field");
+        // NOTE: A failed merge leaves the snapshot unchanged.
+        assert_eq!(
+            json_report(&snapshot.report()),
+            json_report(&analyze(&LINES, false).report())
+        );
+    }
+
+    #[test]
+    fn invalid_snapshots() {
+        let json = to_json(&analyze(&LINES, false).snapshot());
+        assert!(from_json(&json).is_ok());
+        let mut wrong_format = json.clone();
+        wrong_format["format"] = json!("something else");
+        assert_eq!(
+            error(from_json(&wrong_format)),
+            "The input is not a snapshot"
+        );
+        let mut wrong_version = json.clone();
+        wrong_version["version"] = json!(SNAPSHOT_VERSION - 1);
+        assert_eq!(
+            error(from_json(&wrong_version)),
+            format!(
+                "The snapshot has version {} but only version {} is supported",
+                SNAPSHOT_VERSION - 1,
+                SNAPSHOT_VERSION
+            )
+        );
+        let mut missing_stats = json.clone();
+        missing_stats.as_object_mut().unwrap().remove("stats");
+        assert!(error(from_json(&missing_stats)).starts_with("The input is not a valid snapshot"));
+        let not_a_snapshot = Snapshot::from_reader(&b"{\"type\":\"a\"}\n"[..]);
+        assert!(error(not_a_snapshot).starts_with("The input is not a valid snapshot"));
+    }
+
+    #[test]
+    fn inconsistent_value_statistics() {
+        let sketch = to_json(&analyze(&LINES, false).snapshot());
+        let exact = to_json(&analyze(&LINES, true).snapshot());
+        let quantiles =
+            |json: &Value| json["stats"]["types"][0]["data"]["values"]["quantiles"].clone();
+        assert!(quantiles(&sketch).get("Sketch").is_some());
+        assert!(quantiles(&exact).get("Exact").is_some());
+        // NOTE: Merging these would mix a sketch with exact values.
+        let mut mixed = sketch.clone();
+        mixed["stats"]["types"][0]["data"]["values"]["quantiles"] = quantiles(&exact);
+        assert_eq!(
+            error(from_json(&mixed)),
+            "The snapshot contains value statistics that do not match its settings"
+        );
+        let mut missing = exact.clone();
+        missing["stats"]["types"][0]["data"]["values"]["quantiles"]["Exact"] = json!([1.0]);
+        assert_eq!(
+            error(from_json(&missing)),
+            "The snapshot contains value statistics that do not match its settings"
+        );
+        let mut empty = sketch;
+        empty["stats"]["types"][0]["data"]["values"]["quantiles"] = json!("Empty");
+        assert_eq!(
+            error(from_json(&empty)),
+            "The snapshot contains value statistics that do not match its settings"
+        );
+    }
+}
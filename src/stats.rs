@@ -0,0 +1,349 @@
+use crate::distinct::DistinctCounter;
+use crate::errors::ErrorStats;
+use crate::key::{GroupKey, Key};
+use crate::process::strip_line_terminator;
+use crate::schema::Schema;
+use crate::values::ValueStats;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Which bytes of a line are counted in `TypeData::bytes`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum ByteMode {
+    /// The content of the line without its terminator
+    Content,
+    /// The content plus one byte for the `\n` terminator, ignoring the `\r` of a `\r\n`
+    Newline,
+    /// The line as stored, including a `\n` or `\r\n` terminator
+    Raw,
+}
+
+impl ByteMode {
+    /// Returns the number of bytes counted for a line including its terminator.
+    pub(crate) fn count(self, line: &[u8]) -> u64 {
+        let content = strip_line_terminator(line);
+        let terminator = line.len() - content.len();
+        let counted = match self {
+            ByteMode::Content => 0,
+            ByteMode::Newline => terminator.min(1),
+            ByteMode::Raw => terminator,
+        };
+        (content.len() + counted) as u64
+    }
+}
+
+/// The statistics of the entries of a type.
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct TypeData {
+    /// The number of entries with this type.
+    pub(crate) num: u64,
+    /// The number of bytes used by all entries with this type.
+    pub(crate) bytes: u64,
+    /// The distribution of the sizes of the entries with this type.
+    pub(crate) sizes: SizeStats,
+    /// The statistics of the numeric field selected with `--value`.
+    pub(crate) values: ValueStats,
+    /// The distinct values of the field selected with `--distinct`.
+    pub(crate) distinct: DistinctCounter,
+    /// The fields of the entries with this type. Only collected by the `schema` subcommand.
+    pub(crate) schema: Schema,
+}
+
+impl TypeData {
+    /// Adds the statistics of `other` to `self`.
+    pub fn merge(&mut self, other: &TypeData) {
+        self.num += other.num;
+        self.bytes += other.bytes;
+        self.sizes.merge(&other.sizes);
+        self.values.merge(&other.values);
+        self.distinct.merge(&other.distinct);
+        self.schema.merge(&other.schema);
+    }
+
+    /// The number of entries.
+    pub fn objects(&self) -> u64 {
+        self.num
+    }
+
+    /// The number of bytes used by the entries. This depends on [`AnalyzerBuilder::bytes`] and
+    /// [`AnalyzerBuilder::count_compressed`].
+    ///
+    /// [`AnalyzerBuilder::bytes`]: crate::AnalyzerBuilder::bytes
+    /// [`AnalyzerBuilder::count_compressed`]: crate::AnalyzerBuilder::count_compressed
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// The distribution of the sizes of the entries.
+    pub fn sizes(&self) -> &SizeStats {
+        &self.sizes
+    }
+
+    /// The statistics of the value field. Empty if no value field was selected.
+    pub fn values(&self) -> &ValueStats {
+        &self.values
+    }
+
+    /// The (possibly estimated) number of distinct values of the distinct field. Zero if no
+    /// distinct field was selected.
+    pub fn distinct(&self) -> u64 {
+        self.distinct.count()
+    }
+
+    /// The fields of the entries. Empty unless [`AnalyzerBuilder::schema`] was enabled.
+    ///
+    /// [`AnalyzerBuilder::schema`]: crate::AnalyzerBuilder::schema
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+}
+
+/// The distribution of the sizes of entries.
+///
+/// The sizes are counted according to the [`ByteMode`] of the analysis. Unlike `TypeData::bytes`,
+/// they are not scaled by [`AnalyzerBuilder::count_compressed`] and always refer to the
+/// decompressed lines.
+///
+/// [`AnalyzerBuilder::count_compressed`]: crate::AnalyzerBuilder::count_compressed
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub struct SizeStats {
+    /// The smallest size. Only meaningful if the histogram is not empty.
+    pub(crate) min: u64,
+    /// The largest size. Only meaningful if the histogram is not empty.
+    pub(crate) max: u64,
+    pub(crate) sum: u64,
+    /// The number of entries by the bit length of their size. That is, bucket 0 contains the
+    /// entries of size 0 and bucket `i > 0` contains the entries with sizes in `2^(i-1)..2^i`.
+    pub(crate) histogram: Vec<u64>,
+}
+
+impl SizeStats {
+    pub fn add(&mut self, size: u64) {
+        if self.histogram.is_empty() {
+            self.min = size;
+            self.max = size;
+        }
+        self.min = self.min.min(size);
+        self.max = self.max.max(size);
+        self.sum += size;
+        let bucket = (u64::BITS - size.leading_zeros()) as usize;
+        if self.histogram.len() <= bucket {
+            self.histogram.resize(bucket + 1, 0);
+        }
+        self.histogram[bucket] += 1;
+    }
+
+    pub fn merge(&mut self, other: &SizeStats) {
+        if other.histogram.is_empty() {
+            return;
+        }
+        if self.histogram.is_empty() {
+            *self = other.clone();
+            return;
+        }
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.sum += other.sum;
+        if self.histogram.len() < other.histogram.len() {
+            self.histogram.resize(other.histogram.len(), 0);
+        }
+        for (n, other) in self.histogram.iter_mut().zip(&other.histogram) {
+            *n += other;
+        }
+    }
+
+    /// The number of entries.
+    pub fn count(&self) -> u64 {
+        self.histogram.iter().sum()
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        let count = self.count();
+        (count > 0).then(|| self.sum as f64 / count as f64)
+    }
+
+    pub fn min(&self) -> Option<u64> {
+        (!self.histogram.is_empty()).then_some(self.min)
+    }
+
+    pub fn max(&self) -> Option<u64> {
+        (!self.histogram.is_empty()).then_some(self.max)
+    }
+
+    /// The total size of the entries.
+    pub fn sum(&self) -> u64 {
+        self.sum
+    }
+
+    /// The number of entries by the bit length of their size. That is, bucket 0 contains the
+    /// entries of size 0 and bucket `i > 0` contains the entries with sizes in the range returned
+    /// by [`size_bucket_range`].
+    pub fn histogram(&self) -> &[u64] {
+        &self.histogram
+    }
+}
+
+/// The range of sizes of a bucket of `SizeStats::histogram`.
+///
+/// The buckets of 64-bit sizes are `0..=64`. Larger buckets are treated as bucket 64.
+pub fn size_bucket_range(bucket: usize) -> (u64, u64) {
+    match bucket.min(u64::BITS as usize) {
+        0 => (0, 0),
+        bucket => (1 << (bucket - 1), u64::MAX >> (u64::BITS as usize - bucket)),
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct Stats {
+    /// The statistics of all entries grouped by their type.
+    #[serde(with = "types")]
+    pub types: HashMap<GroupKey, TypeData>,
+    /// The number of lines that were read, including the ones that were skipped.
+    pub lines: u64,
+    /// The number of lines that were excluded because their timestamps are outside of the time
+    /// range.
+    pub excluded: u64,
+    /// The number of lines that were excluded because they do not match the filters.
+    pub filtered: u64,
+    /// The number of lines that are not valid UTF-8, including the ones that were skipped.
+    pub invalid_utf8: u64,
+    pub errors: ErrorStats,
+    /// The number of decompressed bytes that were read, including line terminators.
+    pub read_bytes: u64,
+    /// The size of the data that was read as stored. This differs from `read_bytes` only for
+    /// compressed inputs.
+    pub input_bytes: u64,
+    /// The number of bytes of the line terminators of the entries that are not counted in
+    /// `TypeData::bytes`.
+    pub terminator_bytes: u64,
+    /// The number of bytes of the lines that were not counted as entries. This is scaled like
+    /// `TypeData::bytes` if the compressed size is counted.
+    pub other_bytes: u64,
+}
+
+impl Stats {
+    pub fn new(errors: ErrorStats) -> Self {
+        Self {
+            types: HashMap::new(),
+            lines: 0,
+            excluded: 0,
+            filtered: 0,
+            invalid_utf8: 0,
+            errors,
+            read_bytes: 0,
+            input_bytes: 0,
+            terminator_bytes: 0,
+            other_bytes: 0,
+        }
+    }
+
+    /// Adds the statistics of `other`, which must refer to lines after the lines in `self`.
+    pub fn merge(&mut self, other: Stats) {
+        for (ty, data) in other.types {
+            self.types.entry(ty).or_default().merge(&data);
+        }
+        self.lines += other.lines;
+        self.excluded += other.excluded;
+        self.filtered += other.filtered;
+        self.invalid_utf8 += other.invalid_utf8;
+        self.errors.merge(other.errors);
+        self.read_bytes += other.read_bytes;
+        self.input_bytes += other.input_bytes;
+        self.terminator_bytes += other.terminator_bytes;
+        self.other_bytes += other.other_bytes;
+    }
+}
+
+/// Serializes the statistics of the types as a list ordered by the group keys.
+mod types {
+    use super::*;
+    use serde::{Deserializer, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    struct Type<K, D> {
+        key: Vec<Component<K>>,
+        data: D,
+    }
+
+    /// A component of a group key. Unlike `Option<Key>`, this distinguishes absent fields from
+    /// fields whose value is null.
+    #[derive(Serialize, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    enum Component<K> {
+        Absent,
+        Value(K),
+    }
+
+    pub fn serialize<S: Serializer>(
+        types: &HashMap<GroupKey, TypeData>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut types: Vec<_> = types.iter().collect();
+        types.sort_by_key(|&(key, _)| key);
+        let types: Vec<_> = types
+            .into_iter()
+            .map(|(key, data)| Type {
+                key: key
+                    .iter()
+                    .map(|c| match c {
+                        Some(key) => Component::Value(key),
+                        None => Component::Absent,
+                    })
+                    .collect(),
+                data,
+            })
+            .collect();
+        types.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<HashMap<GroupKey, TypeData>, D::Error> {
+        let types = Vec::<Type<Key, TypeData>>::deserialize(deserializer)?;
+        let types = types.into_iter().map(|ty| {
+            let key = ty
+                .key
+                .into_iter()
+                .map(|c| match c {
+                    Component::Value(key) => Some(key),
+                    Component::Absent => None,
+                })
+                .collect();
+            (key, ty.data)
+        });
+        Ok(types.collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_ranges() {
+        assert_eq!(size_bucket_range(0), (0, 0));
+        assert_eq!(size_bucket_range(1), (1, 1));
+        assert_eq!(size_bucket_range(2), (2, 3));
+        assert_eq!(size_bucket_range(11), (1024, 2047));
+        assert_eq!(size_bucket_range(64), (1 << 63, u64::MAX));
+        assert_eq!(size_bucket_range(65), (1 << 63, u64::MAX));
+        assert_eq!(size_bucket_range(usize::MAX), (1 << 63, u64::MAX));
+    }
+
+    #[test]
+    fn sizes_are_in_their_bucket_range() {
+        const SIZES: [u64; 8] = [0, 1, 2, 3, 4, 1000, 1 << 40, 1 << 63];
+        let mut sizes = SizeStats::default();
+        for size in SIZES {
+            sizes.add(size);
+        }
+        assert_eq!(sizes.histogram().len(), 65);
+        for size in SIZES {
+            let bucket = (u64::BITS - size.leading_zeros()) as usize;
+            let (min, max) = size_bucket_range(bucket);
+            assert!((min..=max).contains(&size), "{} {}", size, bucket);
+        }
+    }
+}
@@ -0,0 +1,72 @@
+//! Helpers shared by the unit tests of the library.
+
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+
+/// A file in the temporary directory that is deleted when dropped.
+pub struct TempFile(pub PathBuf);
+
+impl TempFile {
+    /// Creates the file. `name` must be unique among the tests of a test binary.
+    pub fn new(name: &str, contents: &[u8]) -> Self {
+        let path =
+            std::env::temp_dir().join(format!("log-analyzer-{}-{}", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        Self(path)
+    }
+
+    pub fn append(&self, data: &[u8]) {
+        let mut file = OpenOptions::new().append(true).open(&self.0).unwrap();
+        file.write_all(data).unwrap();
+    }
+
+    /// Overwrites the file in place so that its identity is kept.
+    pub fn overwrite(&self, contents: &[u8]) {
+        let mut file = OpenOptions::new().write(true).open(&self.0).unwrap();
+        file.set_len(0).unwrap();
+        file.write_all(contents).unwrap();
+    }
+
+    pub fn path(&self) -> &str {
+        self.0.to_str().unwrap()
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// A linear congruential generator that makes generated test data reproducible.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        self.0
+    }
+
+    /// Returns a number in `0..n`.
+    pub fn below(&mut self, n: u64) -> u64 {
+        // NOTE: The low bits of a linear congruential generator have short periods.
+        (self.next() >> 33) % n
+    }
+
+    /// Returns a number in `0.0..1.0`.
+    pub fn fraction(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    pub fn pick<T: Copy>(&mut self, items: &[T]) -> T {
+        items[self.below(items.len() as u64) as usize]
+    }
+}
@@ -0,0 +1,198 @@
+use crate::duration::parse_duration;
+use crate::key::Key;
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, SecondsFormat};
+use serde_json::Value;
+use std::fmt;
+use std::str::FromStr;
+
+/// The format of the values of the timestamp field.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TimestampFormat {
+    /// A string as described in RFC 3339 such as `2022-05-01T12:00:00Z`.
+    Rfc3339,
+    /// The number of seconds since the Unix epoch, possibly with a fractional part.
+    EpochSeconds,
+    /// The number of milliseconds since the Unix epoch.
+    EpochMillis,
+    /// A string in a custom strftime format. Timestamps without a time zone are interpreted as
+    /// UTC.
+    Custom(String),
+}
+
+impl FromStr for TimestampFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rfc3339" => Ok(Self::Rfc3339),
+            "epoch-seconds" => Ok(Self::EpochSeconds),
+            "epoch-millis" => Ok(Self::EpochMillis),
+            _ if s.contains('%') => Ok(Self::Custom(s.to_string())),
+            _ => Err(format!(
+                "`{}` is neither rfc3339, epoch-seconds, epoch-millis, nor a strftime format",
+                s
+            )),
+        }
+    }
+}
+
+/// Formats the timestamp format as accepted by the `FromStr` implementation.
+impl fmt::Display for TimestampFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Rfc3339 => f.write_str("rfc3339"),
+            Self::EpochSeconds => f.write_str("epoch-seconds"),
+            Self::EpochMillis => f.write_str("epoch-millis"),
+            Self::Custom(format) => f.write_str(format),
+        }
+    }
+}
+
+impl TimestampFormat {
+    /// Parses a timestamp and returns the number of milliseconds since the Unix epoch.
+    pub fn parse(&self, value: &Value) -> Result<i64> {
+        match value {
+            Value::String(s) => self.parse_str(s),
+            Value::Number(n) => match self {
+                Self::EpochSeconds | Self::EpochMillis => self.parse_epoch(&n.to_string()),
+                _ => Err(anyhow!("Expected a string but found the number {}", n)),
+            },
+            _ => Err(anyhow!("Expected a string or a number but found {}", value)),
+        }
+    }
+
+    /// Parses the raw json form of a timestamp.
+    pub fn parse_raw(&self, raw: &[u8]) -> Result<i64> {
+        // NOTE: Most timestamps are strings without escape sequences, which can be parsed without
+        // allocating.
+        if let [b'"', s @ .., b'"'] = raw {
+            if !s.contains(&b'\\') {
+                return self.parse_str(std::str::from_utf8(s)?);
+            }
+        }
+        self.parse(&serde_json::from_slice(raw)?)
+    }
+
+    fn parse_str(&self, s: &str) -> Result<i64> {
+        let res = match self {
+            Self::Rfc3339 => DateTime::parse_from_rfc3339(s)
+                .map(|t| t.timestamp_millis())
+                .map_err(|e| e.into()),
+            Self::EpochSeconds | Self::EpochMillis => self.parse_epoch(s),
+            Self::Custom(format) => parse_custom(s, format),
+        };
+        res.with_context(|| format!("Could not parse the timestamp `{}`", s))
+    }
+
+    fn parse_epoch(&self, s: &str) -> Result<i64> {
+        let scale = match self {
+            Self::EpochSeconds => 1000,
+            _ => 1,
+        };
+        if let Ok(n) = s.parse::<i64>() {
+            return n
+                .checked_mul(scale)
+                .ok_or_else(|| anyhow!("The timestamp is out of range"));
+        }
+        let n: f64 = s.parse()?;
+        let millis = (n * scale as f64).floor();
+        // NOTE: The range of i64 milliseconds is far larger than the range of chrono.
+        if !(-1e18..=1e18).contains(&millis) {
+            return Err(anyhow!("The timestamp is out of range"));
+        }
+        Ok(millis as i64)
+    }
+}
+
+fn parse_custom(s: &str, format: &str) -> Result<i64> {
+    if let Ok(t) = DateTime::parse_from_str(s, format) {
+        return Ok(t.timestamp_millis());
+    }
+    // NOTE: The error of the most general attempt is reported if all attempts fail.
+    let err = match NaiveDateTime::parse_from_str(s, format) {
+        Ok(t) => return Ok(t.and_utc().timestamp_millis()),
+        Err(e) => e,
+    };
+    match NaiveDate::parse_from_str(s, format) {
+        Ok(d) => Ok(d.and_time(Default::default()).and_utc().timestamp_millis()),
+        Err(_) => Err(err.into()),
+    }
+}
+
+/// Divides time into buckets of a fixed width that are aligned to the Unix epoch.
+#[derive(Clone, Debug)]
+pub struct Buckets {
+    /// The width of the buckets in milliseconds.
+    width: i64,
+}
+
+impl FromStr for Buckets {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let width = parse_duration(s)?;
+        match i64::try_from(width.as_millis()) {
+            Ok(width) if width > 0 => Ok(Self { width }),
+            _ => Err("The width must be at least 1ms".to_string()),
+        }
+    }
+}
+
+impl Buckets {
+    /// The width of the buckets in milliseconds.
+    pub fn width(&self) -> i64 {
+        self.width
+    }
+
+    /// Returns the start of the bucket containing the timestamp or `None` if the bucket cannot be
+    /// represented.
+    pub fn start(&self, timestamp: i64) -> Option<i64> {
+        let start = timestamp.checked_sub(timestamp.rem_euclid(self.width))?;
+        DateTime::from_timestamp_millis(start).map(|_| start)
+    }
+
+    /// Returns the key component identifying a bucket. `start` must have been returned by
+    /// [`Buckets::start`].
+    ///
+    /// The key is an RFC 3339 string in UTC. All keys use the same precision so that they are
+    /// ordered chronologically.
+    pub fn key(&self, start: i64) -> Key {
+        let precision = match self.width % 1000 {
+            0 => SecondsFormat::Secs,
+            _ => SecondsFormat::Millis,
+        };
+        let start = DateTime::from_timestamp_millis(start).expect("validated by Buckets::start");
+        Key(Value::String(start.to_rfc3339_opts(precision, true)))
+    }
+}
+
+/// Parses a point in time for `--since` and `--until` and returns the number of milliseconds
+/// since the Unix epoch.
+///
+/// This is either an RFC 3339 timestamp, a date or date and time without a time zone (interpreted
+/// as UTC), or a duration preceded by `-` that is subtracted from the current time.
+pub fn parse_time(s: &str) -> Result<i64, String> {
+    if let Some(duration) = s.strip_prefix('-') {
+        let duration = parse_duration(duration)?;
+        let millis = i64::try_from(duration.as_millis()).map_err(|_| "Duration is too large")?;
+        return Ok(chrono::Utc::now().timestamp_millis().saturating_sub(millis));
+    }
+    if let Ok(t) = DateTime::parse_from_rfc3339(s) {
+        return Ok(t.timestamp_millis());
+    }
+    for format in ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"] {
+        if let Ok(t) = NaiveDateTime::parse_from_str(s, format) {
+            return Ok(t.and_utc().timestamp_millis());
+        }
+    }
+    if let Ok(d) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        return Ok(d.and_time(Default::default()).and_utc().timestamp_millis());
+    }
+    Err(format!(
+        "`{}` is neither a timestamp such as `2022-05-01T12:00:00Z` nor a relative time such as \
+         `-2h`",
+        s
+    ))
+}
@@ -0,0 +1,438 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// The quantiles reported for numeric fields.
+pub const QUANTILES: [f64; 3] = [0.5, 0.9, 0.99];
+
+/// Summary statistics of the values of a numeric field.
+///
+/// The statistics of different parts of the input can be merged. As long as the parts are merged
+/// in the same order, the result is the same.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ValueStats {
+    pub(crate) count: u64,
+    pub(crate) sum: f64,
+    /// The minimum. Only meaningful if `count > 0`.
+    pub(crate) min: f64,
+    /// The maximum. Only meaningful if `count > 0`.
+    pub(crate) max: f64,
+    mean: f64,
+    /// The sum of squared differences from the mean.
+    m2: f64,
+    quantiles: Quantiles,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+enum Quantiles {
+    #[default]
+    Empty,
+    Sketch(Sketch),
+    /// All values in the order in which they were added.
+    Exact(Vec<f64>),
+}
+
+impl Quantiles {
+    /// Replaces the values by a sketch unless they already are one and returns the sketch.
+    fn sketch(&mut self) -> &mut Sketch {
+        match self {
+            Quantiles::Empty => *self = Quantiles::Sketch(Sketch::default()),
+            Quantiles::Exact(values) => {
+                let mut sketch = Sketch::default();
+                for &value in values.iter() {
+                    sketch.add(value);
+                }
+                *self = Quantiles::Sketch(sketch);
+            }
+            Quantiles::Sketch(_) => {}
+        }
+        match self {
+            Quantiles::Sketch(sketch) => sketch,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl ValueStats {
+    /// Adds a value. As long as `exact` is true, all values are stored so that the quantiles can
+    /// be computed exactly. Once a value is added with `exact` false, the stored values are
+    /// replaced by a sketch.
+    pub fn add(&mut self, value: f64, exact: bool) {
+        if self.count == 0 {
+            self.min = value;
+            self.max = value;
+        }
+        self.count += 1;
+        self.sum += value;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+        match &mut self.quantiles {
+            Quantiles::Empty if exact => self.quantiles = Quantiles::Exact(vec![value]),
+            Quantiles::Exact(values) if exact => values.push(value),
+            quantiles => quantiles.sketch().add(value),
+        }
+    }
+
+    /// Adds the values of `other`. Unless both store their values exactly, the result uses a
+    /// sketch.
+    pub fn merge(&mut self, other: &ValueStats) {
+        if other.count == 0 {
+            return;
+        }
+        if self.count == 0 {
+            *self = other.clone();
+            return;
+        }
+        // NOTE: This is the parallel variant of Welford's algorithm by Chan et al.
+        let count = self.count + other.count;
+        let delta = other.mean - self.mean;
+        self.mean += delta * other.count as f64 / count as f64;
+        self.m2 +=
+            other.m2 + delta * delta * (self.count as f64 * other.count as f64) / count as f64;
+        self.count = count;
+        self.sum += other.sum;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        match (&mut self.quantiles, &other.quantiles) {
+            (Quantiles::Exact(l), Quantiles::Exact(r)) => l.extend_from_slice(r),
+            (l, Quantiles::Exact(r)) => {
+                let sketch = l.sketch();
+                for &value in r {
+                    sketch.add(value);
+                }
+            }
+            (l, Quantiles::Sketch(r)) => l.sketch().merge(r),
+            (_, Quantiles::Empty) => {}
+        }
+    }
+
+    /// Returns whether the statistics could have been created by [`ValueStats::add`] with `exact`.
+    /// Statistics read from a snapshot are checked with this before they are merged.
+    pub(crate) fn is_consistent(&self, exact: bool) -> bool {
+        match &self.quantiles {
+            _ if self.count == 0 => true,
+            Quantiles::Empty => false,
+            Quantiles::Sketch(_) => !exact,
+            Quantiles::Exact(values) => exact && values.len() as u64 == self.count,
+        }
+    }
+
+    /// The number of values.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn sum(&self) -> f64 {
+        self.sum
+    }
+
+    pub fn min(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.min)
+    }
+
+    pub fn max(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.max)
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.mean)
+    }
+
+    /// The population standard deviation.
+    pub fn stddev(&self) -> Option<f64> {
+        (self.count > 0).then(|| (self.m2 / self.count as f64).sqrt())
+    }
+
+    /// Returns the value at rank `q * (count - 1)` (rounded down) in the sorted values.
+    ///
+    /// Unless the values are stored exactly, the result has a relative error of at most 1%.
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        let [value] = self.quantiles([q]);
+        value
+    }
+
+    /// Like [`ValueStats::quantile`] for multiple quantiles at once. If the values are stored
+    /// exactly, they are only copied once.
+    pub fn quantiles<const N: usize>(&self, qs: [f64; N]) -> [Option<f64>; N] {
+        if self.count == 0 {
+            return [None; N];
+        }
+        let ranks = qs.map(|q| (q * (self.count - 1) as f64) as u64);
+        let values = match &self.quantiles {
+            Quantiles::Empty => return [None; N],
+            Quantiles::Sketch(sketch) => ranks.map(|rank| sketch.quantile(rank)),
+            Quantiles::Exact(values) => {
+                let mut values = values.clone();
+                ranks.map(|rank| {
+                    // NOTE: Selecting reorders the values but keeps them as a multiset.
+                    let rank = rank as usize;
+                    let (_, value, _) = values.select_nth_unstable_by(rank, |l, r| l.total_cmp(r));
+                    *value
+                })
+            }
+        };
+        values.map(|value| Some(value.clamp(self.min, self.max)))
+    }
+}
+
+/// The relative accuracy of the quantiles computed by [`Sketch`].
+const RELATIVE_ACCURACY: f64 = 0.01;
+
+/// The maximum number of bins per sign. If this is exceeded, the bins of the values with the
+/// smallest magnitudes are merged.
+const MAX_BINS: usize = 2048;
+
+/// A DDSketch (Masson et al., 2019) for computing approximate quantiles in bounded memory.
+///
+/// Values are counted in bins whose boundaries grow exponentially such that every value in a bin
+/// is within the relative accuracy of the representative value of the bin.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct Sketch {
+    /// The number of positive values by bin index.
+    positive: BTreeMap<i32, u64>,
+    /// The number of negative values by the bin index of their absolute value.
+    negative: BTreeMap<i32, u64>,
+    zero: u64,
+}
+
+fn gamma() -> f64 {
+    (1.0 + RELATIVE_ACCURACY) / (1.0 - RELATIVE_ACCURACY)
+}
+
+/// The index of the bin containing the positive value `x`.
+fn bin_index(x: f64) -> i32 {
+    (x.ln() / gamma().ln()).ceil() as i32
+}
+
+/// The representative value of a bin.
+fn bin_value(index: i32) -> f64 {
+    2.0 * gamma().powi(index) / (gamma() + 1.0)
+}
+
+impl Sketch {
+    fn add(&mut self, value: f64) {
+        if value > 0.0 {
+            *self.positive.entry(bin_index(value)).or_default() += 1;
+            collapse(&mut self.positive);
+        } else if value < 0.0 {
+            *self.negative.entry(bin_index(-value)).or_default() += 1;
+            collapse(&mut self.negative);
+        } else {
+            self.zero += 1;
+        }
+    }
+
+    fn merge(&mut self, other: &Sketch) {
+        for (bins, other) in [
+            (&mut self.positive, &other.positive),
+            (&mut self.negative, &other.negative),
+        ] {
+            for (&index, &count) in other {
+                *bins.entry(index).or_default() += count;
+            }
+            collapse(bins);
+        }
+        self.zero += other.zero;
+    }
+
+    /// Returns the approximate value at the given rank starting at 0.
+    fn quantile(&self, rank: u64) -> f64 {
+        let negative = self
+            .negative
+            .iter()
+            .rev()
+            .map(|(&i, &n)| (-bin_value(i), n));
+        let zero = Some((0.0, self.zero));
+        let positive = self.positive.iter().map(|(&i, &n)| (bin_value(i), n));
+        let mut seen = 0;
+        let mut last = 0.0;
+        for (value, count) in negative.chain(zero).chain(positive) {
+            seen += count;
+            last = value;
+            if seen > rank {
+                break;
+            }
+        }
+        last
+    }
+}
+
+/// Merges the lowest bins until there are at most `MAX_BINS` bins.
+fn collapse(bins: &mut BTreeMap<i32, u64>) {
+    while bins.len() > MAX_BINS {
+        let (_, count) = bins.pop_first().unwrap();
+        *bins.first_entry().unwrap().get_mut() += count;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::Rng;
+
+    /// Generates values spanning many orders of magnitude with both signs and some zeros.
+    fn values(n: usize) -> Vec<f64> {
+        let mut rng = Rng::new(0x9e3779b97f4a7c15);
+        (0..n)
+            .map(|_| {
+                let magnitude = 10f64.powf(rng.fraction() * 12.0 - 6.0);
+                match rng.below(10) {
+                    0 => 0.0,
+                    1 | 2 => -magnitude,
+                    _ => magnitude,
+                }
+            })
+            .collect()
+    }
+
+    fn stats(values: &[f64], exact: bool) -> ValueStats {
+        let mut stats = ValueStats::default();
+        for &v in values {
+            stats.add(v, exact);
+        }
+        stats
+    }
+
+    fn exact_quantile(values: &[f64], q: f64) -> f64 {
+        let mut sorted = values.to_vec();
+        sorted.sort_by(|l, r| l.total_cmp(r));
+        sorted[(q * (sorted.len() - 1) as f64) as usize]
+    }
+
+    #[test]
+    fn empty() {
+        let stats = ValueStats::default();
+        assert_eq!(stats.count(), 0);
+        assert_eq!(stats.min(), None);
+        assert_eq!(stats.mean(), None);
+        assert_eq!(stats.stddev(), None);
+        assert_eq!(stats.quantile(0.5), None);
+    }
+
+    #[test]
+    fn moments() {
+        let stats = stats(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0], false);
+        assert_eq!(stats.count(), 8);
+        assert_eq!(stats.sum(), 40.0);
+        assert_eq!(stats.min(), Some(2.0));
+        assert_eq!(stats.max(), Some(9.0));
+        assert_eq!(stats.mean(), Some(5.0));
+        assert_eq!(stats.stddev(), Some(2.0));
+    }
+
+    #[test]
+    fn sketch_accuracy() {
+        let values = values(100_000);
+        let stats = stats(&values, false);
+        for q in [0.0, 0.01, 0.1, 0.25, 0.5, 0.75, 0.9, 0.99, 0.999, 1.0] {
+            let expected = exact_quantile(&values, q);
+            let actual = stats.quantile(q).unwrap();
+            assert!(
+                (actual - expected).abs() <= RELATIVE_ACCURACY * expected.abs(),
+                "quantile {}: {} instead of {}",
+                q,
+                actual,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn exact_quantiles() {
+        let values = values(1000);
+        let stats = stats(&values, true);
+        for q in [0.0, 0.5, 0.9, 0.99, 1.0] {
+            assert_eq!(stats.quantile(q), Some(exact_quantile(&values, q)));
+        }
+        let expected = QUANTILES.map(|q| Some(exact_quantile(&values, q)));
+        assert_eq!(stats.quantiles(QUANTILES), expected);
+    }
+
+    #[test]
+    fn merge_equals_sequential_add() {
+        let values = values(20_000);
+        for exact in [false, true] {
+            let expected = stats(&values, exact);
+            for parts in [1, 2, 3, 7, 100] {
+                let mut merged = ValueStats::default();
+                for chunk in values.chunks(values.len().div_ceil(parts)) {
+                    merged.merge(&stats(chunk, exact));
+                }
+                merged.merge(&ValueStats::default());
+                assert_eq!(merged.count(), expected.count());
+                assert_eq!(merged.min(), expected.min());
+                assert_eq!(merged.max(), expected.max());
+                let close = |l: f64, r: f64| (l - r).abs() <= 1e-9 * r.abs().max(1.0);
+                assert!(close(merged.sum(), expected.sum()));
+                assert!(close(merged.mean().unwrap(), expected.mean().unwrap()));
+                assert!(close(merged.stddev().unwrap(), expected.stddev().unwrap()));
+                for q in QUANTILES {
+                    assert_eq!(merged.quantile(q), expected.quantile(q), "{} parts", parts);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn mixed_exact_and_approximate() {
+        let values = values(5_000);
+        let (l, r) = values.split_at(2_000);
+        let expected = stats(&values, false);
+        for (l_exact, r_exact) in [(true, false), (false, true)] {
+            let mut merged = stats(l, l_exact);
+            merged.merge(&stats(r, r_exact));
+            let mut added = stats(l, l_exact);
+            for &v in r {
+                added.add(v, r_exact);
+            }
+            for q in QUANTILES {
+                assert_eq!(merged.quantile(q), expected.quantile(q));
+                assert_eq!(added.quantile(q), expected.quantile(q));
+            }
+            assert!(merged.is_consistent(false));
+            assert!(added.is_consistent(false));
+        }
+    }
+
+    #[test]
+    fn sketch_merge_is_exact() {
+        let values = values(10_000);
+        let (l, r) = values.split_at(3_000);
+        let mut merged = Sketch::default();
+        let mut other = Sketch::default();
+        for &v in l {
+            merged.add(v);
+        }
+        for &v in r {
+            other.add(v);
+        }
+        merged.merge(&other);
+        let mut expected = Sketch::default();
+        for &v in &values {
+            expected.add(v);
+        }
+        assert_eq!(merged.positive, expected.positive);
+        assert_eq!(merged.negative, expected.negative);
+        assert_eq!(merged.zero, expected.zero);
+    }
+
+    #[test]
+    fn bins_are_bounded() {
+        let mut sketch = Sketch::default();
+        let mut other = Sketch::default();
+        for i in 0..5_000 {
+            sketch.add(1.03f64.powi(i));
+            other.add(1.03f64.powi(-i));
+        }
+        assert_eq!(sketch.positive.len(), MAX_BINS);
+        sketch.merge(&other);
+        assert_eq!(sketch.positive.len(), MAX_BINS);
+        assert_eq!(sketch.positive.values().sum::<u64>(), 10_000);
+        // NOTE: Collapsing merges the smallest values, so the largest stay accurate.
+        let largest = 1.03f64.powi(4_999);
+        let max = sketch.quantile(9_999);
+        assert!((max - largest).abs() <= RELATIVE_ACCURACY * largest);
+    }
+}
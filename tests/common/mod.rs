@@ -0,0 +1,45 @@
+//! Helpers shared by the integration tests.
+
+use std::path::PathBuf;
+
+/// A file in the temporary directory that is deleted when dropped.
+pub struct TempFile(PathBuf);
+
+impl TempFile {
+    /// Creates the file. `name` must be unique among the tests of a test binary.
+    pub fn new(name: &str, contents: &[u8]) -> Self {
+        let path =
+            std::env::temp_dir().join(format!("log-analyzer-{}-{}", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        Self(path)
+    }
+
+    pub fn path(&self) -> &str {
+        self.0.to_str().unwrap()
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// A linear congruential generator that makes generated test data reproducible.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    /// Returns a number in `0..n`.
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        // NOTE: The low bits of a linear congruential generator have short periods.
+        (self.0 >> 33) % n
+    }
+}
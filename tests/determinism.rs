@@ -0,0 +1,101 @@
+//! Checks that the output does not depend on the number of threads or on whether the input is
+//! mapped into memory.
+
+mod common;
+
+use common::{Rng, TempFile};
+use std::fmt::Write as _;
+use std::process::Command;
+
+/// Generates about 4 MB of entries, i.e. several chunks, with floating-point values whose sums
+/// depend on the order of additions and some lines that cannot be processed.
+fn generate_log() -> Vec<u8> {
+    let mut rng = Rng::new(0x2545f4914f6cdd1d);
+    let mut next = |n| rng.below(n);
+    let mut log = String::new();
+    for i in 0..40_000u64 {
+        let ty = ["login", "logout", "click", "view", "error"][next(5) as usize];
+        let value = next(1_000_000) as f64 / 7.0;
+        let user = next(2_000);
+        let terminator = match next(10) {
+            0 => "\r\n",
+            _ => "\n",
+        };
+        match next(100) {
+            0 => log.push_str("not json"),
+            1 => log.push_str(r#"{"no_type": true}"#),
+            // NOTE: An escaped key cannot be scanned, so the line is parsed instead.
+            2 => write!(log, r#"{{"ty\u0070e":"{}","value":{}}}"#, ty, value).unwrap(),
+            _ => write!(
+                log,
+                r#"{{"type":"{}","time":{},"value":{},"user":"u{}","padding":"{}"}}"#,
+                ty,
+                1_650_000_000 + i * 3,
+                value,
+                user,
+                "x".repeat(next(80) as usize)
+            )
+            .unwrap(),
+        }
+        log.push_str(terminator);
+    }
+    log.into_bytes()
+}
+
+fn run(args: &[&str]) -> (Vec<u8>, Vec<u8>) {
+    let output = Command::new(env!("CARGO_BIN_EXE_log-analyzer"))
+        .args(args)
+        .output()
+        .unwrap();
+    (output.stdout, output.stderr)
+}
+
+#[test]
+fn output_does_not_depend_on_threads_or_mmap() {
+    let log = TempFile::new("determinism.log", &generate_log());
+    let file = log.path();
+    let options = [
+        "--format",
+        "json",
+        "--on-error",
+        "skip",
+        "--value",
+        "value",
+        "--distinct",
+        "user",
+        "--timestamp",
+        "time",
+        "--timestamp-format",
+        "epoch-seconds",
+        "--bucket",
+        "1h",
+    ];
+    let mut outputs = vec![];
+    for threads in ["1", "2", "7"] {
+        for mmap in [true, false] {
+            let mut args = options.to_vec();
+            args.extend(["-j", threads]);
+            if !mmap {
+                args.push("--no-mmap");
+            }
+            args.push(file);
+            outputs.push((threads, mmap, run(&args)));
+        }
+    }
+    let (_, _, (expected_stdout, expected_stderr)) = &outputs[0];
+    assert!(expected_stdout.len() > 1000);
+    for (threads, mmap, (stdout, stderr)) in &outputs[1..] {
+        assert!(
+            stdout == expected_stdout,
+            "different report with -j{} (mmap: {})",
+            threads,
+            mmap
+        );
+        assert!(
+            stderr == expected_stderr,
+            "different summary with -j{} (mmap: {})",
+            threads,
+            mmap
+        );
+    }
+}